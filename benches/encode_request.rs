@@ -0,0 +1,54 @@
+// Encodes the same request repeatedly into one reused buffer. After the
+// first iteration the buffer has enough capacity, so the loop measures
+// encoding work rather than allocator traffic.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rsfcgi::fcgi::{encode_request_into, BeginRequest, KeyValuePair, RoleType};
+
+fn params() -> Vec<KeyValuePair> {
+    [
+        ("GATEWAY_INTERFACE", "CGI/1.1"),
+        ("SERVER_SOFTWARE", "Crustaceous/trunk"),
+        ("SERVER_ADDR", "127.0.0.1"),
+        ("SERVER_PROTOCOL", "HTTP/1.1"),
+        ("REQUEST_METHOD", "POST"),
+        ("REMOTE_ADDR", "127.0.0.1"),
+        ("SCRIPT_FILENAME", "/var/www/html/index.php"),
+        ("CONTENT_TYPE", "application/x-www-form-urlencoded"),
+        ("CONTENT_LENGTH", "1024"),
+    ]
+    .iter()
+    .map(|(k, v)| KeyValuePair::new(k.to_string(), v.to_string()))
+    .collect()
+}
+
+fn encode_reused_buffer(c: &mut Criterion) {
+    let begin = BeginRequest::new(RoleType::Responder, 0, [0; 5]);
+    let params = params();
+    let body = vec![b'a'; 1024];
+    let mut buf: Vec<u8> = Vec::new();
+
+    c.bench_function("encode_request_into reused buffer", |b| {
+        b.iter(|| {
+            encode_request_into(&mut buf, 1, &begin, &params, &body).unwrap();
+            black_box(buf.len());
+        })
+    });
+}
+
+fn encode_fresh_buffer(c: &mut Criterion) {
+    let begin = BeginRequest::new(RoleType::Responder, 0, [0; 5]);
+    let params = params();
+    let body = vec![b'a'; 1024];
+
+    c.bench_function("encode_request_into fresh buffer", |b| {
+        b.iter(|| {
+            let mut buf: Vec<u8> = Vec::new();
+            encode_request_into(&mut buf, 1, &begin, &params, &body).unwrap();
+            black_box(buf.len());
+        })
+    });
+}
+
+criterion_group!(benches, encode_reused_buffer, encode_fresh_buffer);
+criterion_main!(benches);
@@ -0,0 +1,265 @@
+// Server's request/response cycle over a tokio stream, for async web
+// servers where a blocking FastCGI call would hold up a runtime thread:
+//
+//     let mut server = AsyncServer::connect_unix("/run/php/php-fpm.sock", params).await?;
+//     let response = server.execute(b"").await?;
+//
+// Requests are encoded with RequestBuilder and responses parsed with the
+// same codec types the blocking Server uses, so the two put the same
+// bytes on the wire. Any AsyncRead + AsyncWrite stream will do.
+//
+// Responses get the same record checks as Server::read_response: response
+// limits, request ids (strict or not), record types and closed streams.
+// Requests don't get the checks of Server::send_params and execute
+// (params limits, Authorizer bodies, CONTENT_LENGTH), and there is no
+// on_stderr hook, execute_timed or consume_response_with.
+
+use std::io::Error;
+#[cfg(unix)]
+use std::path::Path;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+use super::encoder::check_data_allowed_for;
+use super::server::{
+    check_request_id, check_response_type, check_unknown_type, connect_error,
+    DEFAULT_MAX_RESPONSE_BYTES, DEFAULT_MAX_RESPONSE_RECORDS,
+};
+use super::{
+    EndRequest, FcgiError, FcgiResponse, HeaderView, PaddingPolicy, RecordType, RequestBuilder,
+    RoleType, FCGI_VERSION_1, KEEP_CONN,
+};
+
+pub struct AsyncServer<C: AsyncRead + AsyncWrite + Unpin> {
+    params: Vec<(String, String)>,
+    stream: C,
+    role: RoleType,
+    keep_conn: bool,
+    padding: PaddingPolicy,
+    max_record_len: u16,
+    max_response_bytes: usize,
+    max_response_records: usize,
+    strict_request_ids: bool,
+}
+
+#[cfg(unix)]
+impl AsyncServer<UnixStream> {
+    pub async fn connect_unix<P: AsRef<Path>>(
+        path: P,
+        params_raw: Vec<(String, String)>,
+    ) -> Result<AsyncServer<UnixStream>, FcgiError> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path)
+            .await
+            .map_err(|e| connect_error(e, &path.to_string_lossy()))?;
+
+        Ok(AsyncServer::new(params_raw, stream))
+    }
+}
+
+impl AsyncServer<TcpStream> {
+    // `addr` is host:port.
+    pub async fn connect_tcp(
+        addr: &str,
+        params_raw: Vec<(String, String)>,
+    ) -> Result<AsyncServer<TcpStream>, FcgiError> {
+        let stream = TcpStream::connect(addr).await.map_err(|e| connect_error(e, addr))?;
+
+        Ok(AsyncServer::new(params_raw, stream))
+    }
+}
+
+impl<C: AsyncRead + AsyncWrite + Unpin> AsyncServer<C> {
+    pub fn new(params_raw: Vec<(String, String)>, stream: C) -> AsyncServer<C> {
+        AsyncServer::with_role(params_raw, stream, RoleType::Responder)
+    }
+
+    pub fn with_role(
+        params_raw: Vec<(String, String)>,
+        stream: C,
+        role: RoleType,
+    ) -> AsyncServer<C> {
+        AsyncServer {
+            params: params_raw,
+            stream,
+            role,
+            keep_conn: false,
+            padding: PaddingPolicy::None,
+            max_record_len: u16::MAX,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            max_response_records: DEFAULT_MAX_RESPONSE_RECORDS,
+            strict_request_ids: true,
+        }
+    }
+
+    pub fn role(&self) -> RoleType {
+        self.role
+    }
+
+    pub fn set_keep_conn(&mut self, keep_conn: bool) {
+        self.keep_conn = keep_conn;
+    }
+
+    // As Server::set_padding_policy.
+    pub fn set_padding_policy(&mut self, padding: PaddingPolicy) {
+        self.padding = padding;
+    }
+
+    // As Server::set_max_record_len; 0 is taken as 1.
+    pub fn set_max_record_len(&mut self, max_len: u16) {
+        self.max_record_len = max_len.max(1);
+    }
+
+    // As Server::set_response_limits.
+    pub fn set_response_limits(&mut self, max_bytes: usize, max_records: usize) {
+        self.max_response_bytes = max_bytes;
+        self.max_response_records = max_records;
+    }
+
+    // As Server::set_strict_request_ids.
+    pub fn set_strict_request_ids(&mut self, strict: bool) {
+        self.strict_request_ids = strict;
+    }
+
+    pub fn request_flags(&self) -> u8 {
+        if self.keep_conn {
+            KEEP_CONN
+        } else {
+            0
+        }
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> C {
+        self.stream
+    }
+
+    pub async fn send_request(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.stream.write_all(&bytes).await?;
+        self.stream.flush().await
+    }
+
+    // One complete request and its response, as Server::execute.
+    pub async fn execute(&mut self, body: &[u8]) -> Result<FcgiResponse, FcgiError> {
+        let bytes = self.request(body, &[])?;
+        self.send_request(bytes).await?;
+
+        self.consume_response().await
+    }
+
+    // A complete Filter request, as Server::execute_filter.
+    pub async fn execute_filter(
+        &mut self,
+        stdin: &[u8],
+        data: &[u8],
+    ) -> Result<FcgiResponse, FcgiError> {
+        check_data_allowed_for(self.role)?;
+        let bytes = self.request(stdin, data)?;
+        self.send_request(bytes).await?;
+
+        self.consume_response().await
+    }
+
+    // Reads records up to EndRequest, as Server::read_response.
+    pub async fn consume_response(&mut self) -> Result<FcgiResponse, FcgiError> {
+        let mut response = FcgiResponse::default();
+        let mut stdout_closed = false;
+        let mut stderr_closed = false;
+        let mut bytes = 0;
+        let mut records = 0;
+
+        loop {
+            let (record_type, request_id, record_body) = match self.read_record().await {
+                Ok(r) => r,
+                Err(FcgiError::Io(source)) if response.protocol_version != 0 => {
+                    response.parse_headers();
+                    return Err(FcgiError::Incomplete {
+                        partial: response,
+                        source,
+                    });
+                }
+                // Closed or timed out before a single record: there is no
+                // partial response to hand back.
+                Err(e) => return Err(e),
+            };
+            bytes += record_body.len();
+            records += 1;
+            if bytes > self.max_response_bytes || records > self.max_response_records {
+                return Err(FcgiError::ResponseTooLarge { bytes, records });
+            }
+
+            check_unknown_type(record_type, &record_body)?;
+            if !self.strict_request_ids && request_id != 1 {
+                continue;
+            }
+            check_request_id(request_id, 1)?;
+            check_response_type(record_type, &record_body)?;
+            response.protocol_version = FCGI_VERSION_1;
+
+            if record_type == RecordType::Stdout as u8 {
+                if stdout_closed {
+                    let msg = "Stdout record after end of stream";
+                    return Err(FcgiError::Protocol(String::from(msg)));
+                }
+                stdout_closed = record_body.is_empty();
+                response.stdout.extend(record_body);
+            } else if record_type == RecordType::Stderr as u8 {
+                if stderr_closed {
+                    let msg = "Stderr record after end of stream";
+                    return Err(FcgiError::Protocol(String::from(msg)));
+                }
+                stderr_closed = record_body.is_empty();
+                response.stderr.extend(record_body);
+            } else if record_type == RecordType::EndRequest as u8 {
+                let end = EndRequest::from_bytes(&record_body)?;
+                response.app_status = end.app_status();
+                response.protocol_status = end.protocol_status();
+                response.end_request = Some(end);
+                break;
+            }
+        }
+
+        response.parse_headers();
+        Ok(response)
+    }
+
+    fn request(&self, stdin: &[u8], data: &[u8]) -> Result<Vec<u8>, FcgiError> {
+        RequestBuilder::new()
+            .role(self.role)
+            .flags(self.request_flags())
+            .padding(self.padding)
+            .max_record_len(self.max_record_len)
+            .params(self.params.clone())
+            .stdin(stdin.to_vec())
+            .data(data.to_vec())
+            .build()
+    }
+
+    // One record's type, request id and content; the padding is read and
+    // dropped.
+    async fn read_record(&mut self) -> Result<(u8, u16, Vec<u8>), FcgiError> {
+        let mut header = [0; 8];
+        self.stream.read_exact(&mut header).await?;
+
+        let view = HeaderView::new(&header);
+        if view.version() != FCGI_VERSION_1 {
+            return Err(FcgiError::UnsupportedVersion(view.version()));
+        }
+
+        let mut body = vec![0; view.body_length()];
+        self.stream.read_exact(&mut body).await?;
+        body.truncate(view.content_length().into());
+
+        Ok((view.type_byte(), view.request_id(), body))
+    }
+}
@@ -0,0 +1,168 @@
+// The response's stdout as a `Read`, for bodies too large to hold in
+// memory. Stdout content is handed out as records arrive; Stderr records
+// met along the way are kept aside in `stderr` rather than mixed into the
+// body. EOF is reported only once EndRequest has been read, whether or
+// not the app sent the empty Stdout record first, and every read after
+// that returns Ok(0) as well.
+//
+// Server::stream_response_with is the push-style equivalent, handing each
+// record to a callback as it arrives, and Server::records the lowest
+// level, yielding the records themselves.
+
+use std::io::{Error, ErrorKind, Read, Write};
+
+use super::{EndRequest, FcgiError, ProtocolStatus, Record, RecordType, Server};
+
+pub struct ResponseBody<'a, C: Read + Write> {
+    server: &'a mut Server<C>,
+    // Content of the Stdout record being read, and how far into it we are.
+    current: Vec<u8>,
+    pos: usize,
+    stderr: Vec<u8>,
+    end: Option<EndRequest>,
+}
+
+impl<'a, C: Read + Write> ResponseBody<'a, C> {
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr
+    }
+
+    // The EndRequest record, once the body has been read to EOF.
+    pub fn end_request(&self) -> Option<&EndRequest> {
+        self.end.as_ref()
+    }
+
+    // Stops reading early; see Server::cancel_streaming. Does nothing if
+    // the body was already read to EOF.
+    pub fn cancel(self) -> Result<(), FcgiError> {
+        if self.end.is_some() {
+            return Ok(());
+        }
+
+        self.server.cancel_streaming()
+    }
+
+    // Reads the next record and returns whether there's body to hand out.
+    fn fill(&mut self) -> Result<bool, FcgiError> {
+        while self.end.is_none() {
+            let (record_type, record_body) = self.server.read_app_record()?;
+
+            if record_type == RecordType::Stdout as u8 {
+                if !record_body.is_empty() {
+                    self.current = record_body;
+                    self.pos = 0;
+                    return Ok(true);
+                }
+            } else if record_type == RecordType::Stderr as u8 {
+                self.server.take_stderr(record_body, &mut self.stderr);
+            } else if record_type == RecordType::EndRequest as u8 {
+                self.end = Some(EndRequest::from_bytes(&record_body)?);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl<'a, C: Read + Write> Read for ResponseBody<'a, C> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.pos == self.current.len() {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(FcgiError::Io(e)) => return Err(e),
+                Err(e) => return Err(Error::new(ErrorKind::InvalidData, e)),
+            }
+        }
+
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+impl<C: Read + Write> Server<C> {
+    // Streams the response to a request already sent on this connection.
+    pub fn response_body(&mut self) -> ResponseBody<'_, C> {
+        ResponseBody {
+            server: self,
+            current: Vec::new(),
+            pos: 0,
+            stderr: Vec::new(),
+            end: None,
+        }
+    }
+}
+
+// One step of a response as seen by Server::stream_response_with.
+#[derive(Debug)]
+pub enum StreamEvent<'a> {
+    Stdout(&'a [u8]),
+    Stderr(&'a [u8]),
+    End {
+        app_status: u32,
+        protocol_status: ProtocolStatus,
+    },
+}
+
+impl<C: Read + Write> Server<C> {
+    // Calls `f` for every Stdout and Stderr record as soon as it is read,
+    // e.g. to forward a long-running script's progress lines live, and
+    // finally with End. Empty records closing a stream are not reported.
+    pub fn stream_response_with<F: FnMut(StreamEvent)>(&mut self, mut f: F) -> Result<(), FcgiError> {
+        loop {
+            let (record_type, record_body) = self.read_app_record()?;
+
+            if record_type == RecordType::Stdout as u8 && !record_body.is_empty() {
+                f(StreamEvent::Stdout(&record_body));
+            } else if record_type == RecordType::Stderr as u8 && !record_body.is_empty() {
+                f(StreamEvent::Stderr(&record_body));
+            } else if record_type == RecordType::EndRequest as u8 {
+                let end = EndRequest::from_bytes(&record_body)?;
+                f(StreamEvent::End {
+                    app_status: end.app_status(),
+                    protocol_status: end.protocol_status(),
+                });
+                return Ok(());
+            }
+        }
+    }
+}
+
+// The records of a response in the order they arrive, EndRequest last.
+// Nothing is buffered beyond the record being read.
+pub struct Records<'a, C: Read + Write> {
+    server: &'a mut Server<C>,
+    done: bool,
+}
+
+impl<'a, C: Read + Write> Iterator for Records<'a, C> {
+    type Item = Result<Record, FcgiError>;
+
+    fn next(&mut self) -> Option<Result<Record, FcgiError>> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.server.read_record();
+        // An error ends the iteration. After a WouldBlock or a timeout
+        // nothing is lost, and a fresh records() carries on.
+        self.done = match &record {
+            Ok(record) => record.record_type() == RecordType::EndRequest,
+            Err(_) => true,
+        };
+
+        Some(record)
+    }
+}
+
+impl<C: Read + Write> Server<C> {
+    pub fn records(&mut self) -> Records<'_, C> {
+        Records {
+            server: self,
+            done: false,
+        }
+    }
+}
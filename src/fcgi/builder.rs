@@ -0,0 +1,244 @@
+// Fluent construction of a connected Server:
+//
+//     let server: Server<UnixStream> = Server::builder("/run/php/php8.2-fpm.sock")
+//         .param("GATEWAY_INTERFACE", "CGI/1.1")
+//         .param("REQUEST_METHOD", "GET")
+//         .keep_alive(true)
+//         .connect()?;
+//
+// The stream type picks the transport: UnixStream treats the address as a
+// socket path, TcpStream as host:port.
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::io::{ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use super::server::{connect_tcp_timeout, StreamControl};
+use super::{FcgiError, PaddingPolicy, RoleType, Server, TcpOptions};
+
+// Streams that Server::builder knows how to open from an address. With a
+// timeout, a connect that hasn't finished by then fails with TimedOut.
+pub trait Connect: Sized {
+    fn connect_to(addr: &str, timeout: Option<Duration>) -> Result<Self, FcgiError>;
+}
+
+#[cfg(unix)]
+impl Connect for UnixStream {
+    fn connect_to(addr: &str, timeout: Option<Duration>) -> Result<UnixStream, FcgiError> {
+        match timeout {
+            Some(timeout) => super::server::connect_unix_timeout(addr, timeout),
+            None => UnixStream::connect(addr).map_err(|e| super::server::connect_error(e, addr)),
+        }
+    }
+}
+
+impl Connect for TcpStream {
+    fn connect_to(addr: &str, timeout: Option<Duration>) -> Result<TcpStream, FcgiError> {
+        connect_tcp_timeout(addr, timeout)
+    }
+}
+
+const TLS_PARAMS: [(&str, &str); 3] = [
+    ("HTTPS", "on"),
+    ("REQUEST_SCHEME", "https"),
+    ("SERVER_PORT", "443"),
+];
+
+pub struct ServerBuilder<C> {
+    addr: String,
+    params: Vec<(String, String)>,
+    role: RoleType,
+    padding: PaddingPolicy,
+    read_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    keep_alive: bool,
+    max_params: Option<usize>,
+    max_params_bytes: Option<usize>,
+    max_response: Option<(usize, usize)>,
+    strict_request_ids: bool,
+    max_record_len: u16,
+    tls: bool,
+    tcp: TcpOptions,
+    stream: PhantomData<C>,
+}
+
+impl<C: Read + Write + StreamControl + Connect> ServerBuilder<C> {
+    pub fn new(addr: &str) -> ServerBuilder<C> {
+        ServerBuilder {
+            addr: addr.to_string(),
+            params: Vec::new(),
+            role: RoleType::Responder,
+            padding: PaddingPolicy::default(),
+            read_timeout: None,
+            connect_timeout: None,
+            keep_alive: false,
+            max_params: None,
+            max_params_bytes: None,
+            max_response: None,
+            strict_request_ids: true,
+            max_record_len: u16::MAX,
+            tls: false,
+            tcp: TcpOptions::default(),
+            stream: PhantomData,
+        }
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> ServerBuilder<C> {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn role(mut self, role: RoleType) -> ServerBuilder<C> {
+        self.role = role;
+        self
+    }
+
+    pub fn padding(mut self, padding: PaddingPolicy) -> ServerBuilder<C> {
+        self.padding = padding;
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> ServerBuilder<C> {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> ServerBuilder<C> {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn keep_alive(mut self, keep_alive: bool) -> ServerBuilder<C> {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    pub fn max_params(mut self, max_pairs: usize) -> ServerBuilder<C> {
+        self.max_params = Some(max_pairs);
+        self
+    }
+
+    // Limit on the encoded size of all params together.
+    pub fn max_params_bytes(mut self, max_bytes: usize) -> ServerBuilder<C> {
+        self.max_params_bytes = Some(max_bytes);
+        self
+    }
+
+    // See Server::set_response_limits.
+    pub fn response_limits(mut self, max_bytes: usize, max_records: usize) -> ServerBuilder<C> {
+        self.max_response = Some((max_bytes, max_records));
+        self
+    }
+
+    // See Server::set_strict_request_ids.
+    pub fn strict_request_ids(mut self, strict: bool) -> ServerBuilder<C> {
+        self.strict_request_ids = strict;
+        self
+    }
+
+    pub fn max_record_len(mut self, max_len: u16) -> ServerBuilder<C> {
+        self.max_record_len = max_len;
+        self
+    }
+
+    // For requests that reached us over TLS: adds HTTPS=on,
+    // REQUEST_SCHEME=https and SERVER_PORT=443, which PHP apps check to
+    // decide between http:// and https:// URLs. Any of the three set with
+    // param, before or after, wins.
+    pub fn tls(mut self, tls: bool) -> ServerBuilder<C> {
+        self.tls = tls;
+        self
+    }
+
+    // Applied to TCP connections, and ignored for Unix sockets.
+    pub fn tcp_options(mut self, options: TcpOptions) -> ServerBuilder<C> {
+        self.tcp = options;
+        self
+    }
+
+    fn request_params(&self) -> Vec<(String, String)> {
+        let mut params = self.params.clone();
+        if self.tls {
+            for (name, value) in TLS_PARAMS {
+                if !params.iter().any(|(k, _)| k == name) {
+                    params.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+
+        params
+    }
+
+    // Opens a new connection with these settings. The builder is kept, so
+    // one can stand for a backend and open as many connections as needed.
+    pub fn connect(&self) -> Result<Server<C>, FcgiError> {
+        let started = Instant::now();
+        let stream = C::connect_to(&self.addr, self.connect_timeout)?;
+        let connect_time = started.elapsed();
+        if self.read_timeout.is_some() {
+            stream.set_read_timeout(self.read_timeout)?;
+        }
+        if self.tcp != TcpOptions::default() {
+            stream.set_tcp_options(&self.tcp)?;
+        }
+
+        let mut server = Server::with_role(self.request_params(), stream, self.role);
+        server.set_connect_time(connect_time);
+        server.set_padding_policy(self.padding);
+        server.set_keep_conn(self.keep_alive);
+        server.set_params_limits(self.max_params, self.max_params_bytes);
+        server.set_strict_request_ids(self.strict_request_ids);
+        if let Some((max_bytes, max_records)) = self.max_response {
+            server.set_response_limits(max_bytes, max_records);
+        }
+        server.set_max_record_len(self.max_record_len);
+        server.check_params_limits()?;
+        server.addr = Some((self.addr.clone(), self.connect_timeout));
+
+        Ok(server)
+    }
+}
+
+impl<C: Read + Write + StreamControl + Connect> Server<C> {
+    pub fn builder(addr: &str) -> ServerBuilder<C> {
+        ServerBuilder::new(addr)
+    }
+
+    // send_request for a long-lived connection whose app may have been
+    // restarted since it was last used. If the write fails because the
+    // connection is gone (broken pipe, reset), this reconnects to the
+    // address the builder connected to and sends `bytes` again, up to
+    // `max_retries` times. Only the send is ever retried: call it before
+    // reading anything of the response, never once part of it has
+    // arrived, or the app could run the request twice. Connections not
+    // opened by ServerBuilder::connect have no address to go back to, and
+    // fail as send_request would.
+    pub fn send_request_retrying(&mut self, bytes: Vec<u8>, max_retries: usize) -> Result<(), FcgiError> {
+        let mut retries = 0;
+        loop {
+            let err = match self.send_request(bytes.clone()) {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            let gone = matches!(
+                err.kind(),
+                ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+            );
+            let (addr, timeout) = match &self.addr {
+                Some(addr) if gone && retries < max_retries => addr.clone(),
+                _ => return Err(FcgiError::Io(err)),
+            };
+
+            let read_timeout = self.read_timeout().ok().flatten();
+            let stream = C::connect_to(&addr, timeout)?;
+            if read_timeout.is_some() {
+                stream.set_read_timeout(read_timeout)?;
+            }
+            self.replace_stream(stream);
+            retries += 1;
+        }
+    }
+}
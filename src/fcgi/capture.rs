@@ -0,0 +1,182 @@
+// Recording a live FastCGI conversation and replaying it offline. Wrap the
+// connection in a CaptureStream and every chunk written or read is
+// appended to a capture file:
+//
+//     let stream = CaptureStream::create(UnixStream::connect(path)?, "fpm.fcgi")?;
+//     let mut server = Server::from_stream(params, stream);
+//     server.execute(b"")?;
+//
+// and later, without the app, `replay("fpm.fcgi")?` parses the recorded
+// response just as read_response would have.
+//
+// The file is the magic FCGICAP1 followed by one entry per chunk: a
+// direction byte ('>' sent to the app, '<' received from it), the chunk
+// length as a big-endian u32, then the bytes. Chunks are as the socket
+// delivered them, so record boundaries are not preserved.
+
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use super::server::{StreamControl, TcpOptions};
+use super::{FcgiError, FcgiResponse, Server};
+
+const MAGIC: &[u8; 8] = b"FCGICAP1";
+const SENT: u8 = b'>';
+const RECEIVED: u8 = b'<';
+
+pub struct CaptureStream<C> {
+    inner: C,
+    file: File,
+}
+
+impl<C: Read + Write> CaptureStream<C> {
+    // Truncates `path` if it exists.
+    pub fn create<P: AsRef<Path>>(inner: C, path: P) -> Result<CaptureStream<C>, Error> {
+        let mut file = File::create(path)?;
+        file.write_all(MAGIC)?;
+
+        Ok(CaptureStream { inner, file })
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn record(&mut self, direction: u8, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let len = u32::try_from(data.len()).map_err(|_| Error::from(ErrorKind::InvalidInput))?;
+        self.file.write_all(&[direction])?;
+        self.file.write_all(&len.to_be_bytes())?;
+        self.file.write_all(data)
+    }
+}
+
+impl<C: Read + Write> Read for CaptureStream<C> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let n = self.inner.read(buf)?;
+        self.record(RECEIVED, &buf[..n])?;
+
+        Ok(n)
+    }
+}
+
+impl<C: Read + Write> Write for CaptureStream<C> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let n = self.inner.write(data)?;
+        self.record(SENT, &data[..n])?;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()?;
+        self.file.flush()
+    }
+}
+
+impl<C: Read + Write + StreamControl> StreamControl for CaptureStream<C> {
+    fn close_write(&mut self) -> Result<(), Error> {
+        self.inner.close_write()
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.inner.close()
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn read_timeout(&self) -> Result<Option<Duration>, Error> {
+        self.inner.read_timeout()
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    fn peer_closed(&mut self, wait: Duration) -> Result<bool, Error> {
+        self.inner.peer_closed(wait)
+    }
+
+    fn set_tcp_options(&self, options: &TcpOptions) -> Result<(), Error> {
+        self.inner.set_tcp_options(options)
+    }
+}
+
+// The two sides of a capture file, each reassembled into one buffer.
+#[derive(Debug, Default)]
+pub struct Capture {
+    pub sent: Vec<u8>,
+    pub received: Vec<u8>,
+}
+
+impl Capture {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Capture, FcgiError> {
+        let bytes = std::fs::read(path)?;
+        let mut rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| FcgiError::Protocol(String::from("not a capture file")))?;
+
+        let mut capture = Capture::default();
+        while let Some((&direction, after)) = rest.split_first() {
+            let truncated = || FcgiError::Protocol(String::from("capture file is truncated"));
+            let (len, after) = after.split_first_chunk::<4>().ok_or_else(truncated)?;
+            let len = u32::from_be_bytes(*len) as usize;
+            if after.len() < len {
+                return Err(truncated());
+            }
+            let (data, after) = after.split_at(len);
+
+            match direction {
+                SENT => capture.sent.extend_from_slice(data),
+                RECEIVED => capture.received.extend_from_slice(data),
+                other => {
+                    return Err(FcgiError::Protocol(format!("bad capture direction byte {}", other)))
+                }
+            }
+            rest = after;
+        }
+
+        Ok(capture)
+    }
+}
+
+// The first response in a capture file, parsed offline.
+pub fn replay<P: AsRef<Path>>(path: P) -> Result<FcgiResponse, FcgiError> {
+    let capture = Capture::load(path)?;
+    let stream = ReplayStream {
+        received: std::io::Cursor::new(capture.received),
+    };
+
+    Server::from_stream(Vec::new(), stream).read_response()
+}
+
+// Serves the recorded replies and throws away anything written.
+struct ReplayStream {
+    received: std::io::Cursor<Vec<u8>>,
+}
+
+impl Read for ReplayStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.received.read(buf)
+    }
+}
+
+impl Write for ReplayStream {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+// Decodes an HTTP/1.1 `Transfer-Encoding: chunked` request body, for
+// proxying uploads whose length isn't known up front. Chunk extensions
+// are ignored and trailer fields are read and dropped. The result is a
+// plain `Read` of the body bytes, which can go straight to
+// Server::send_stdin_from.
+// https://datatracker.ietf.org/doc/html/rfc9112#section-7.1
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+
+// Longest chunk size line or trailer line accepted, line ending included.
+// Without a cap a client sending no line ending would have the whole
+// stream buffered in search of one.
+const MAX_LINE_LEN: usize = 8192;
+
+pub struct ChunkedBodyReader<R: Read> {
+    inner: BufReader<R>,
+    // Bytes left in the current chunk.
+    remaining: u64,
+    // A chunk's data has been read but not the CRLF after it.
+    need_crlf: bool,
+    done: bool,
+    decoded: u64,
+}
+
+impl<R: Read> ChunkedBodyReader<R> {
+    pub fn new(inner: R) -> ChunkedBodyReader<R> {
+        ChunkedBodyReader {
+            inner: BufReader::new(inner),
+            remaining: 0,
+            need_crlf: false,
+            done: false,
+            decoded: 0,
+        }
+    }
+
+    // Body bytes decoded so far; once read returns 0 this is the value
+    // for CONTENT_LENGTH.
+    pub fn decoded_len(&self) -> u64 {
+        self.decoded
+    }
+
+    // Reading is buffered, so bytes after the body (a pipelined next
+    // request, say) may already have been pulled from `inner`; they are
+    // still in the returned BufReader.
+    pub fn into_inner(self) -> BufReader<R> {
+        self.inner
+    }
+
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = String::new();
+        let limit = MAX_LINE_LEN as u64 + 1;
+        if (&mut self.inner).take(limit).read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "chunked body ended early"));
+        }
+        if line.len() > MAX_LINE_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "chunk size or trailer line too long"));
+        }
+
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    // Moves to the next chunk, returning false after the last chunk and
+    // its trailers.
+    fn next_chunk(&mut self) -> Result<bool, Error> {
+        if self.need_crlf {
+            if !self.read_line()?.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidData, "missing CRLF after chunk"));
+            }
+            self.need_crlf = false;
+        }
+
+        let line = self.read_line()?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("bad chunk size {:?}", size)))?;
+
+        if size == 0 {
+            while !self.read_line()?.is_empty() {}
+            self.done = true;
+            return Ok(false);
+        }
+
+        self.remaining = size;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for ChunkedBodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 && !self.next_chunk()? {
+            return Ok(0);
+        }
+
+        let want = buf.len().min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "chunked body ended early"));
+        }
+
+        self.remaining -= n as u64;
+        self.need_crlf = self.remaining == 0;
+        self.decoded += n as u64;
+
+        Ok(n)
+    }
+}
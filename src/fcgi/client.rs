@@ -0,0 +1,28 @@
+// Blocking one-shot client for thread-per-request servers:
+//
+//     let response = FastCgiClient::connect("127.0.0.1:9000")?
+//         .send(params, b"")?;
+//
+// Each client carries a single request over its own connection, which is
+// closed once the response has been read.
+
+use std::net::{TcpStream, ToSocketAddrs};
+
+use super::server::connect_tcp_timeout;
+use super::{FcgiError, FcgiResponse, Server};
+
+pub struct FastCgiClient {
+    stream: TcpStream,
+}
+
+impl FastCgiClient {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<FastCgiClient, FcgiError> {
+        let stream = connect_tcp_timeout(addr, None)?;
+        Ok(FastCgiClient { stream })
+    }
+
+    pub fn send(self, params: Vec<(String, String)>, body: &[u8]) -> Result<FcgiResponse, FcgiError> {
+        let mut server = Server::from_stream(params, self.stream);
+        server.execute(body)
+    }
+}
@@ -0,0 +1,984 @@
+// Wire format types. Nothing here touches I/O, apart from the std-only
+// Record::write_to, so this module only needs `alloc` and builds without
+// std.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::FcgiError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    BeginRequest = 1,
+    AbortRequest = 2,
+    EndRequest = 3,
+    Params = 4,
+    Stdin = 5,
+    Stdout = 6,
+    Stderr = 7,
+    Data = 8,
+    GetValues = 9,
+    GetValuesResult = 10,
+    UnknownType = 11,
+}
+
+impl RecordType {
+    // Management records concern the connection rather than a request and
+    // always use FCGI_NULL_REQUEST_ID.
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S4
+    pub fn is_management(&self) -> bool {
+        matches!(
+            self,
+            RecordType::GetValues | RecordType::GetValuesResult | RecordType::UnknownType
+        )
+    }
+
+    // The spec's constant name, for logs that line up with other
+    // implementations'.
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S8
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordType::BeginRequest => "FCGI_BEGIN_REQUEST",
+            RecordType::AbortRequest => "FCGI_ABORT_REQUEST",
+            RecordType::EndRequest => "FCGI_END_REQUEST",
+            RecordType::Params => "FCGI_PARAMS",
+            RecordType::Stdin => "FCGI_STDIN",
+            RecordType::Stdout => "FCGI_STDOUT",
+            RecordType::Stderr => "FCGI_STDERR",
+            RecordType::Data => "FCGI_DATA",
+            RecordType::GetValues => "FCGI_GET_VALUES",
+            RecordType::GetValuesResult => "FCGI_GET_VALUES_RESULT",
+            RecordType::UnknownType => "FCGI_UNKNOWN_TYPE",
+        }
+    }
+}
+
+impl TryFrom<u8> for RecordType {
+    type Error = FcgiError;
+
+    fn try_from(byte: u8) -> Result<RecordType, FcgiError> {
+        match byte {
+            1 => Ok(RecordType::BeginRequest),
+            2 => Ok(RecordType::AbortRequest),
+            3 => Ok(RecordType::EndRequest),
+            4 => Ok(RecordType::Params),
+            5 => Ok(RecordType::Stdin),
+            6 => Ok(RecordType::Stdout),
+            7 => Ok(RecordType::Stderr),
+            8 => Ok(RecordType::Data),
+            9 => Ok(RecordType::GetValues),
+            10 => Ok(RecordType::GetValuesResult),
+            11 => Ok(RecordType::UnknownType),
+            other => Err(FcgiError::Protocol(format!("Unknown record type {}", other))),
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// A record type byte as read off the wire. Bytes outside the spec's
+// 1..=11 keep their raw value instead of being folded into UnknownType,
+// which is a real record type of its own (FCGI_UNKNOWN_TYPE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParsedRecordType {
+    Known(RecordType),
+    Unknown(u8),
+}
+
+impl ParsedRecordType {
+    pub fn from_u8(byte: u8) -> ParsedRecordType {
+        match RecordType::try_from(byte) {
+            Ok(known) => ParsedRecordType::Known(known),
+            Err(_) => ParsedRecordType::Unknown(byte),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ParsedRecordType::Known(t) => format!("{} record", t.name()),
+            ParsedRecordType::Unknown(b) => format!("unknown record type {}", b),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Header {
+    version: u8,
+    record_type: RecordType,
+    request_id_hi: u8,
+    request_id_lo: u8,
+    content_length_hi: u8,
+    content_length_lo: u8,
+    padding_length: u8,
+    reserved: u8,
+}
+
+// How many padding bytes follow a record's content.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S3.3
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PaddingPolicy {
+    // No padding at all; useful for byte-exact comparisons in tests.
+    #[default]
+    None,
+    // Pad so that content plus padding is a multiple of 8 bytes, as the
+    // spec recommends.
+    Align8,
+    // Always use exactly this many padding bytes.
+    Fixed(u8),
+}
+
+impl PaddingPolicy {
+    pub fn padding_length(&self, content_length: usize) -> u8 {
+        match self {
+            PaddingPolicy::None => 0,
+            PaddingPolicy::Align8 => ((8 - (content_length % 8)) % 8) as u8,
+            PaddingPolicy::Fixed(n) => *n,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Record {
+    header: Header,
+    content_data: Vec<u8>,
+    padding_data: Vec<u8>,
+    // Kept so set_content can size the padding for new content the same
+    // way it was sized at construction.
+    padding_policy: PaddingPolicy,
+}
+
+// Records are equal when they'd go on the wire as the same bytes; the
+// padding policy they were built with doesn't count.
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        self.header == other.header
+            && self.content_data == other.content_data
+            && self.padding_data == other.padding_data
+    }
+}
+
+impl Eq for Record {}
+
+impl core::hash::Hash for Record {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.header.hash(state);
+        self.content_data.hash(state);
+        self.padding_data.hash(state);
+    }
+}
+
+// Read-only view of a record header sitting in some buffer: fields are
+// read by offset straight out of the borrowed bytes, nothing is copied.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S3.3
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderView<'a> {
+    bytes: &'a [u8; 8],
+}
+
+impl<'a> HeaderView<'a> {
+    pub fn new(bytes: &'a [u8; 8]) -> HeaderView<'a> {
+        HeaderView { bytes }
+    }
+
+    // Views the first 8 bytes of `buf`; None if it's shorter than that.
+    pub fn parse(buf: &'a [u8]) -> Option<HeaderView<'a>> {
+        buf.first_chunk::<8>().map(HeaderView::new)
+    }
+
+    pub fn version(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn type_byte(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    pub fn record_type(&self) -> ParsedRecordType {
+        ParsedRecordType::from_u8(self.bytes[1])
+    }
+
+    pub fn request_id(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    pub fn content_length(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[4], self.bytes[5]])
+    }
+
+    pub fn padding_length(&self) -> u8 {
+        self.bytes[6]
+    }
+
+    // Content plus padding: how many bytes follow the header.
+    pub fn body_length(&self) -> usize {
+        usize::from(self.content_length()) + usize::from(self.padding_length())
+    }
+}
+
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S3.3
+// Management records (GetValues and friends) use request id 0.
+pub const FCGI_NULL_REQUEST_ID: u16 = 0;
+
+// The only protocol version the spec defines, sent as the first byte of
+// every record header.
+pub const FCGI_VERSION_1: u8 = 1;
+
+// Every constructor, and set_content, fills padding_data with exactly
+// header.padding_length zeros, so the header never claims more or less
+// padding than is written. Prefer record_aligned, or record_with_padding,
+// to choosing a padding length by hand.
+impl Record {
+    pub fn record_from_data(
+        record_type: RecordType,
+        content_data: Vec<u8>,
+        padding_length: u8,
+    ) -> Result<Self, FcgiError> {
+        Record::record_for_request(record_type, content_data, padding_length, 1)
+    }
+
+    pub fn record_for_request(
+        record_type: RecordType,
+        content_data: Vec<u8>,
+        padding_length: u8,
+        request_id: u16,
+    ) -> Result<Self, FcgiError> {
+        let content_length = content_data.len();
+
+        if content_length > u16::MAX.into() {
+            return Err(FcgiError::ContentTooLong);
+        }
+
+        if record_type.is_management() != (request_id == FCGI_NULL_REQUEST_ID) {
+            return Err(FcgiError::Protocol(format!(
+                "{} record cannot use request id {}",
+                record_type.name(),
+                request_id
+            )));
+        }
+
+        // Fits in a u16 after the check above, so the cast keeps every bit.
+        let [content_length_hi, content_length_lo] = (content_length as u16).to_be_bytes();
+
+        // NOTE: record_from_data sets request ID to 1 for every request.
+        //       this mirrors the behavior of nginx. Every request
+        //       must have its own connection.
+        let [request_id_hi, request_id_lo] = request_id.to_be_bytes();
+        let header = Header {
+            version: FCGI_VERSION_1,
+            record_type,
+            request_id_hi,
+            request_id_lo,
+            content_length_hi,
+            content_length_lo,
+            padding_length,
+            reserved: 0,
+        };
+
+        let padding_data: Vec<u8> = vec![0; padding_length.into()];
+
+        Ok(Self {
+            header,
+            content_data,
+            padding_data,
+            padding_policy: PaddingPolicy::Fixed(padding_length),
+        })
+    }
+
+    pub fn record_with_padding(
+        record_type: RecordType,
+        content_data: Vec<u8>,
+        padding: PaddingPolicy,
+    ) -> Result<Self, FcgiError> {
+        let padding_length = padding.padding_length(content_data.len());
+        let mut record = Record::record_from_data(record_type, content_data, padding_length)?;
+        record.padding_policy = padding;
+
+        Ok(record)
+    }
+
+    // A stream's content as records of at most u16::MAX bytes each, in
+    // order. Params, Stdin and the other streams are simply the
+    // concatenation of their records' content, so data of any length can
+    // be sent this way. Empty data gives no records; the empty record
+    // closing the stream is not included.
+    pub fn split_stream(
+        record_type: RecordType,
+        data: &[u8],
+        padding: PaddingPolicy,
+    ) -> Result<Vec<Record>, FcgiError> {
+        data.chunks(u16::MAX.into())
+            .map(|chunk| Record::record_with_padding(record_type, chunk.to_vec(), padding))
+            .collect()
+    }
+
+    // Parses one record off the front of `buf`, returning it and the
+    // number of bytes it took up, padding included. A buffer that stops
+    // short of the whole record, or a type byte the spec doesn't define,
+    // is an error.
+    pub fn from_bytes(buf: &[u8]) -> Result<(Record, usize), FcgiError> {
+        let view = HeaderView::parse(buf)
+            .ok_or_else(|| FcgiError::Protocol(String::from("Record header truncated")))?;
+        let record_type = match view.record_type() {
+            ParsedRecordType::Known(t) => t,
+            ParsedRecordType::Unknown(b) => {
+                return Err(FcgiError::Protocol(format!("unknown record type {}", b)))
+            }
+        };
+
+        let content_end = 8 + usize::from(view.content_length());
+        let used = 8 + view.body_length();
+        if buf.len() < used {
+            let msg = format!("Record truncated: {} of {} bytes", buf.len(), used);
+            return Err(FcgiError::Protocol(msg));
+        }
+
+        let header = Header {
+            version: view.version(),
+            record_type,
+            request_id_hi: buf[2],
+            request_id_lo: buf[3],
+            content_length_hi: buf[4],
+            content_length_lo: buf[5],
+            padding_length: view.padding_length(),
+            reserved: buf[7],
+        };
+        let record = Record {
+            header,
+            content_data: buf[8..content_end].to_vec(),
+            padding_data: buf[content_end..used].to_vec(),
+            padding_policy: PaddingPolicy::Fixed(view.padding_length()),
+        };
+
+        Ok((record, used))
+    }
+
+    // Padded with zeros to a multiple of 8 bytes, as the spec recommends;
+    // record_with_padding(.., PaddingPolicy::Align8) spelled out.
+    pub fn record_aligned(record_type: RecordType, content_data: Vec<u8>) -> Result<Self, FcgiError> {
+        Record::record_with_padding(record_type, content_data, PaddingPolicy::Align8)
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        self.header.record_type
+    }
+
+    pub fn request_id(&self) -> u16 {
+        u16::from_be_bytes([self.header.request_id_hi, self.header.request_id_lo])
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content_data
+    }
+
+    // Replaces the content, e.g. to rewrite a Params record in a
+    // middleware, updating the content length and padding to match.
+    pub fn set_content(&mut self, content_data: Vec<u8>) -> Result<(), FcgiError> {
+        let content_length = content_data.len();
+        if content_length > u16::MAX.into() {
+            return Err(FcgiError::ContentTooLong);
+        }
+
+        let padding_length = self.padding_policy.padding_length(content_length);
+        let [content_length_hi, content_length_lo] = (content_length as u16).to_be_bytes();
+
+        self.header.content_length_hi = content_length_hi;
+        self.header.content_length_lo = content_length_lo;
+        self.header.padding_length = padding_length;
+        self.content_data = content_data;
+        self.padding_data = vec![0; padding_length.into()];
+
+        Ok(())
+    }
+
+    // Content length straight from a raw 8-byte header, for framing code
+    // that only peeks the header to decide how much more to read.
+    pub fn content_length_from_header_bytes(header: &[u8; 8]) -> u16 {
+        HeaderView::new(header).content_length()
+    }
+
+    pub fn to_vec_u8(&self) -> Vec<u8> {
+        self.debug_check_padding();
+        let len = 8 + self.content_data.len() + self.padding_data.len();
+        let mut output: Vec<u8> = Vec::with_capacity(len);
+
+        output.extend_from_slice(&self.header_bytes());
+        output.extend(&self.content_data);
+        output.extend(&self.padding_data);
+
+        output
+    }
+
+    // Writes the record straight to `w`, with no intermediate buffer.
+    // Wrap a socket in a BufWriter first, or each record is three writes.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.debug_check_padding();
+        w.write_all(&self.header_bytes())?;
+        w.write_all(&self.content_data)?;
+        w.write_all(&self.padding_data)
+    }
+
+    fn debug_check_padding(&self) {
+        debug_assert_eq!(
+            self.padding_data.len(),
+            usize::from(self.header.padding_length),
+            "padding_data out of step with the header's padding length"
+        );
+    }
+
+    pub fn header_bytes(&self) -> [u8; 8] {
+        [
+            self.header.version,
+            self.header.record_type as u8,
+            self.header.request_id_hi,
+            self.header.request_id_lo,
+            self.header.content_length_hi,
+            self.header.content_length_lo,
+            self.header.padding_length,
+            self.header.reserved,
+        ]
+    }
+}
+
+// Special request body types
+
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S3.4
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyValuePair {
+    name: String,
+    value: String,
+}
+
+impl KeyValuePair {
+    pub fn new(name: String, value: String) -> KeyValuePair {
+        KeyValuePair { name, value }
+    }
+
+    // Like new, refusing what the app would see as a different variable
+    // or none at all once it builds its environment from the pair: a name
+    // that is empty, contains '=' or a control character (NUL included),
+    // or a value containing NUL. Any other text is fine in a value.
+    pub fn new_checked(name: String, value: String) -> Result<KeyValuePair, FcgiError> {
+        if name.is_empty() || name.chars().any(|c| c == '=' || c.is_control()) {
+            return Err(FcgiError::InvalidParamName(name));
+        }
+        if value.contains('\0') {
+            return Err(FcgiError::InvalidParamValue(name));
+        }
+
+        Ok(KeyValuePair { name, value })
+    }
+
+    pub fn as_pair_ref(&self) -> KeyValuePairRef<'_> {
+        KeyValuePairRef::new(&self.name, &self.value)
+    }
+
+    pub fn to_vec_u8(&self) -> Result<Vec<u8>, FcgiError> {
+        self.as_pair_ref().to_vec_u8()
+    }
+
+    // Size of the pair once encoded, length prefixes included.
+    pub fn encoded_len(&self) -> usize {
+        self.as_pair_ref().encoded_len()
+    }
+
+    // Appends the encoded pair to `output`.
+    pub fn write_into(&self, output: &mut Vec<u8>) -> Result<(), FcgiError> {
+        self.as_pair_ref().write_into(output)
+    }
+
+    // Decodes one pair from the front of `buf`, returning it along with
+    // the number of bytes it occupied.
+    pub fn from_bytes(buf: &[u8]) -> Result<(KeyValuePair, usize), FcgiError> {
+        let (name_size, name_len_bytes) = read_pair_length(buf)?;
+        let (value_size, value_len_bytes) = read_pair_length(&buf[name_len_bytes..])?;
+
+        let start = name_len_bytes + value_len_bytes;
+        let total = start + name_size + value_size;
+        if buf.len() < total {
+            return Err(FcgiError::Protocol(String::from("Name or value truncated")));
+        }
+
+        let name = String::from_utf8(buf[start..start + name_size].to_vec());
+        let value = String::from_utf8(buf[start + name_size..total].to_vec());
+
+        match (name, value) {
+            (Ok(name), Ok(value)) => Ok((KeyValuePair { name, value }, total)),
+            _ => Err(FcgiError::Protocol(String::from("Name or value is not valid UTF-8"))),
+        }
+    }
+
+    // Decodes a whole name-value stream, e.g. the concatenated bodies of
+    // a Params or GetValuesResult stream. Each pair's name and value length
+    // is one byte if its high bit is clear and four bytes if set, chosen
+    // independently, so all four combinations can appear.
+    pub fn parse_all(buf: &[u8]) -> Result<Vec<KeyValuePair>, FcgiError> {
+        let mut pairs: Vec<KeyValuePair> = Vec::new();
+        let mut offset = 0;
+        while offset < buf.len() {
+            let (kv, used) = KeyValuePair::from_bytes(&buf[offset..])?;
+            pairs.push(kv);
+            offset += used;
+        }
+
+        Ok(pairs)
+    }
+
+    // True when `buf` ends exactly on a pair boundary, i.e. no pair has
+    // been cut short by a record boundary.
+    pub fn pairs_complete(buf: &[u8]) -> bool {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let (name_size, name_len_bytes) = match read_pair_length(&buf[offset..]) {
+                Ok(l) => l,
+                Err(_) => return false,
+            };
+            let (value_size, value_len_bytes) =
+                match read_pair_length(&buf[offset + name_len_bytes..]) {
+                    Ok(l) => l,
+                    Err(_) => return false,
+                };
+            offset += name_len_bytes + value_len_bytes + name_size + value_size;
+        }
+
+        offset == buf.len()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+// A name-value pair over borrowed strings, for encoding params straight
+// from the caller's data without copying them into a KeyValuePair first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyValuePairRef<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+impl<'a> KeyValuePairRef<'a> {
+    pub fn new(name: &'a str, value: &'a str) -> KeyValuePairRef<'a> {
+        KeyValuePairRef { name, value }
+    }
+
+    pub fn to_vec_u8(&self) -> Result<Vec<u8>, FcgiError> {
+        let mut output: Vec<u8> = Vec::with_capacity(self.encoded_len());
+        self.write_into(&mut output)?;
+
+        Ok(output)
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let prefix = |len: usize| if len > 0x7F { 4 } else { 1 };
+        prefix(self.name.len()) + prefix(self.value.len()) + self.name.len() + self.value.len()
+    }
+
+    pub fn write_into(&self, output: &mut Vec<u8>) -> Result<(), FcgiError> {
+        write_pair_length(output, self.name.len())?;
+        write_pair_length(output, self.value.len())?;
+
+        output.extend_from_slice(self.name.as_bytes());
+        output.extend_from_slice(self.value.as_bytes());
+
+        Ok(())
+    }
+
+    pub fn to_owned_pair(&self) -> KeyValuePair {
+        KeyValuePair::new(self.name.to_string(), self.value.to_string())
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+}
+
+impl<'a> From<&'a KeyValuePair> for KeyValuePairRef<'a> {
+    fn from(kv: &'a KeyValuePair) -> KeyValuePairRef<'a> {
+        kv.as_pair_ref()
+    }
+}
+
+// Lengths up to 127 fit in one byte. Anything longer uses four bytes,
+// big-endian, with the high bit of the first one set as the marker, which
+// leaves 31 bits for the length itself. Compared as u64 so this holds
+// whatever the width of usize.
+fn write_pair_length(output: &mut Vec<u8>, len: usize) -> Result<(), FcgiError> {
+    if len as u64 > 0x7FFF_FFFF {
+        return Err(FcgiError::NameOrValueTooLarge);
+    }
+
+    if len > 0x7F {
+        output.extend_from_slice(&((len as u32) | 0x8000_0000).to_be_bytes());
+    } else {
+        output.push(len as u8);
+    }
+
+    Ok(())
+}
+
+// Reads a 1- or 4-byte name/value length, returning the length and how
+// many bytes encoded it.
+fn read_pair_length(buf: &[u8]) -> Result<(usize, usize), FcgiError> {
+    match buf.first() {
+        None => Err(FcgiError::Protocol(String::from("Missing name or value length"))),
+        Some(b) if b & 0x80 == 0 => Ok((*b as usize, 1)),
+        Some(_) => {
+            if buf.len() < 4 {
+                let msg = "Truncated 4-byte name or value length";
+                return Err(FcgiError::Protocol(String::from(msg)));
+            }
+            let size = (((buf[0] & 0x7F) as usize) << 24)
+                | ((buf[1] as usize) << 16)
+                | ((buf[2] as usize) << 8)
+                | buf[3] as usize;
+            Ok((size, 4))
+        }
+    }
+}
+
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S5.1
+// BeginRequest flag asking the app to keep the connection open after
+// EndRequest so it can take the next request.
+pub const KEEP_CONN: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RoleType {
+    Responder = 1,
+    Authorizer = 2,
+    Filter = 3,
+}
+
+impl TryFrom<u16> for RoleType {
+    type Error = FcgiError;
+
+    fn try_from(role: u16) -> Result<RoleType, FcgiError> {
+        match role {
+            1 => Ok(RoleType::Responder),
+            2 => Ok(RoleType::Authorizer),
+            3 => Ok(RoleType::Filter),
+            other => Err(FcgiError::Protocol(format!("Unknown role {}", other))),
+        }
+    }
+}
+
+impl TryFrom<u8> for RoleType {
+    type Error = FcgiError;
+
+    fn try_from(role: u8) -> Result<RoleType, FcgiError> {
+        RoleType::try_from(u16::from(role))
+    }
+}
+
+impl RoleType {
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S8
+    pub fn name(&self) -> &'static str {
+        match self {
+            RoleType::Responder => "FCGI_RESPONDER",
+            RoleType::Authorizer => "FCGI_AUTHORIZER",
+            RoleType::Filter => "FCGI_FILTER",
+        }
+    }
+}
+
+impl fmt::Display for RoleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BeginRequest {
+    role: RoleType,
+    flags: u8,
+    reserved: [u8; 5],
+}
+
+impl BeginRequest {
+    pub fn new(role: RoleType, flags: u8, reserved: [u8; 5]) -> BeginRequest {
+        BeginRequest {
+            role,
+            flags,
+            reserved,
+        }
+    }
+
+    pub fn to_vec_u8(&self) -> Result<Vec<u8>, FcgiError> {
+        let mut output: Vec<u8> = Vec::with_capacity(8);
+        self.write_into(&mut output);
+
+        Ok(output)
+    }
+
+    pub fn write_into(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&(self.role as u16).to_be_bytes());
+        output.push(self.flags);
+        output.extend_from_slice(&self.reserved);
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<BeginRequest, FcgiError> {
+        if buf.len() < 8 {
+            return Err(FcgiError::Protocol(String::from("BeginRequest body too short")));
+        }
+
+        let role = RoleType::try_from(u16::from_be_bytes([buf[0], buf[1]]))?;
+
+        let mut reserved = [0; 5];
+        reserved.copy_from_slice(&buf[3..8]);
+
+        Ok(BeginRequest {
+            role,
+            flags: buf[2],
+            reserved,
+        })
+    }
+
+    pub fn role(&self) -> RoleType {
+        self.role
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn keep_conn(&self) -> bool {
+        self.flags & KEEP_CONN != 0
+    }
+}
+
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S5.5
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ProtocolStatus {
+    #[default]
+    RequestComplete = 0,
+    CantMpxConn = 1,
+    Overloaded = 2,
+    UnknownRole = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EndRequest {
+    app_status: u32,
+    protocol_status: ProtocolStatus,
+}
+
+impl EndRequest {
+    pub fn new(app_status: u32, protocol_status: ProtocolStatus) -> EndRequest {
+        EndRequest {
+            app_status,
+            protocol_status,
+        }
+    }
+
+    pub fn to_vec_u8(&self) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        output.extend_from_slice(&self.app_status.to_be_bytes());
+        output.push(self.protocol_status as u8);
+        output.extend_from_slice(&[0; 3]);
+
+        output
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<EndRequest, FcgiError> {
+        if buf.len() < 8 {
+            return Err(FcgiError::Protocol(String::from("EndRequest body too short")));
+        }
+
+        let app_status = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let protocol_status = match buf[4] {
+            0 => ProtocolStatus::RequestComplete,
+            1 => ProtocolStatus::CantMpxConn,
+            2 => ProtocolStatus::Overloaded,
+            3 => ProtocolStatus::UnknownRole,
+            other => {
+                let msg = format!("Unknown protocol status {}", other);
+                return Err(FcgiError::Protocol(msg));
+            }
+        };
+
+        Ok(EndRequest {
+            app_status,
+            protocol_status,
+        })
+    }
+
+    pub fn app_status(&self) -> u32 {
+        self.app_status
+    }
+
+    pub fn protocol_status(&self) -> ProtocolStatus {
+        self.protocol_status
+    }
+}
+
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S4.1
+// A GetValues body is a name-value stream whose values are all empty.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GetValues {
+    names: Vec<String>,
+}
+
+impl GetValues {
+    pub fn new(names: Vec<String>) -> GetValues {
+        GetValues { names }
+    }
+
+    pub fn to_vec_u8(&self) -> Result<Vec<u8>, FcgiError> {
+        let mut output: Vec<u8> = Vec::new();
+        for name in self.names.iter() {
+            KeyValuePairRef::new(name, "").write_into(&mut output)?;
+        }
+
+        Ok(output)
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<GetValues, FcgiError> {
+        let names = KeyValuePair::parse_all(buf)?
+            .into_iter()
+            .map(|kv| kv.name)
+            .collect();
+
+        Ok(GetValues { names })
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+// A record with its body decoded according to its type, so a response
+// can be handled with a plain match. Params and GetValuesResult decode
+// only if the record holds whole pairs; a pair split across records
+// needs the stream reassembled first (see KeyValuePair::pairs_complete).
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum FcgiRecord {
+    BeginRequest(BeginRequest),
+    AbortRequest,
+    EndRequest(EndRequest),
+    Params(Vec<KeyValuePair>),
+    Stdin(Vec<u8>),
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    Data(Vec<u8>),
+    GetValues(GetValues),
+    GetValuesResult(Vec<KeyValuePair>),
+    // The record type byte the app didn't recognize.
+    UnknownType(u8),
+}
+
+impl Record {
+    pub fn decode(&self) -> Result<FcgiRecord, FcgiError> {
+        let content = &self.content_data;
+        let decoded = match self.header.record_type {
+            RecordType::BeginRequest => FcgiRecord::BeginRequest(BeginRequest::from_bytes(content)?),
+            RecordType::AbortRequest => FcgiRecord::AbortRequest,
+            RecordType::EndRequest => FcgiRecord::EndRequest(EndRequest::from_bytes(content)?),
+            RecordType::Params => FcgiRecord::Params(KeyValuePair::parse_all(content)?),
+            RecordType::Stdin => FcgiRecord::Stdin(content.clone()),
+            RecordType::Stdout => FcgiRecord::Stdout(content.clone()),
+            RecordType::Stderr => FcgiRecord::Stderr(content.clone()),
+            RecordType::Data => FcgiRecord::Data(content.clone()),
+            RecordType::GetValues => FcgiRecord::GetValues(GetValues::from_bytes(content)?),
+            RecordType::GetValuesResult => FcgiRecord::GetValuesResult(KeyValuePair::parse_all(content)?),
+            RecordType::UnknownType => match content.first() {
+                Some(&unknown) => FcgiRecord::UnknownType(unknown),
+                None => {
+                    let msg = "UnknownType body too short";
+                    return Err(FcgiError::Protocol(String::from(msg)));
+                }
+            },
+        };
+
+        Ok(decoded)
+    }
+}
+
+// Encodes a complete request into a caller-owned buffer: BeginRequest,
+// the pairs as one Params stream in records of at most u16::MAX bytes, the
+// empty Params record, Stdin likewise, and the empty Stdin record. The
+// buffer is cleared first but keeps its capacity, so a hot loop that
+// reuses one buffer stops allocating once it has grown to fit its requests.
+pub fn encode_request_into(
+    buf: &mut Vec<u8>,
+    request_id: u16,
+    begin: &BeginRequest,
+    params: &[KeyValuePair],
+    stdin: &[u8],
+) -> Result<(), FcgiError> {
+    buf.clear();
+
+    let start = begin_record_into(buf, RecordType::BeginRequest, request_id);
+    begin.write_into(buf);
+    end_record_into(buf, start)?;
+
+    let start = begin_record_into(buf, RecordType::Params, request_id);
+    for kv in params {
+        kv.write_into(buf)?;
+    }
+    let content_length = buf.len() - start - 8;
+    if content_length > u16::MAX.into() {
+        // Cut wherever the limit falls, pair boundaries or not. Only params
+        // this large pay for moving the stream to make room for headers.
+        let stream = buf.split_off(start + 8);
+        buf.truncate(start);
+        for chunk in stream.chunks(u16::MAX.into()) {
+            let start = begin_record_into(buf, RecordType::Params, request_id);
+            buf.extend_from_slice(chunk);
+            end_record_into(buf, start)?;
+        }
+    } else if content_length > 0 {
+        end_record_into(buf, start)?;
+    } else {
+        buf.truncate(start);
+    }
+    let start = begin_record_into(buf, RecordType::Params, request_id);
+    end_record_into(buf, start)?;
+
+    for chunk in stdin.chunks(u16::MAX.into()) {
+        let start = begin_record_into(buf, RecordType::Stdin, request_id);
+        buf.extend_from_slice(chunk);
+        end_record_into(buf, start)?;
+    }
+    let start = begin_record_into(buf, RecordType::Stdin, request_id);
+    end_record_into(buf, start)?;
+
+    Ok(())
+}
+
+// Writes a header with a zero content length and returns where it starts;
+// end_record_into fills in the length once the content is in place.
+fn begin_record_into(buf: &mut Vec<u8>, record_type: RecordType, request_id: u16) -> usize {
+    let start = buf.len();
+    let [id_hi, id_lo] = request_id.to_be_bytes();
+    buf.extend_from_slice(&[FCGI_VERSION_1, record_type as u8, id_hi, id_lo, 0, 0, 0, 0]);
+
+    start
+}
+
+fn end_record_into(buf: &mut [u8], start: usize) -> Result<(), FcgiError> {
+    let content_length = buf.len() - start - 8;
+    if content_length > u16::MAX.into() {
+        return Err(FcgiError::ContentTooLong);
+    }
+
+    let [hi, lo] = (content_length as u16).to_be_bytes();
+    buf[start + 4] = hi;
+    buf[start + 5] = lo;
+
+    Ok(())
+}
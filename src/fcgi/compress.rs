@@ -0,0 +1,62 @@
+// Compressed request bodies. FastCGI has no way for an app to advertise
+// what it accepts, so only use this when you know the app (or the script
+// behind it) decodes CONTENT_ENCODING; php-fpm, for one, does not.
+
+use std::io::Write;
+
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+
+use super::{FcgiError, ParamsEncoder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    // The CONTENT_ENCODING value, as in HTTP's Content-Encoding.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+// Compresses `body` and returns it with the CONTENT_LENGTH that should go
+// with it, which is the compressed size.
+pub fn compress_body(body: &[u8], encoding: Encoding) -> (Vec<u8>, usize) {
+    // Writing into a Vec can't fail, so neither can the encoders.
+    let compressed = match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).expect("writing to a Vec cannot fail");
+            encoder.finish().expect("writing to a Vec cannot fail")
+        }
+        Encoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).expect("writing to a Vec cannot fail");
+            encoder.finish().expect("writing to a Vec cannot fail")
+        }
+    };
+    let len = compressed.len();
+
+    (compressed, len)
+}
+
+impl ParamsEncoder {
+    // Finishes the request with `body` compressed: adds CONTENT_ENCODING
+    // and the compressed CONTENT_LENGTH, closes Params and writes the
+    // Stdin stream. Don't also set either param yourself.
+    pub fn body_compressed(self, body: &[u8], encoding: Encoding) -> Result<Vec<u8>, FcgiError> {
+        let (compressed, len) = compress_body(body, encoding);
+
+        self.param("CONTENT_ENCODING", encoding.name())?
+            .param("CONTENT_LENGTH", &len.to_string())?
+            .params_done()?
+            .stdin(&compressed)?
+            .finish()
+    }
+}
@@ -0,0 +1,124 @@
+// Encode/decode symmetry checks for the body types. Each known value is
+// serialized with to_vec_u8, parsed back with from_bytes, and compared
+// with the original, so a decoder drifting from its encoder shows up here.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+
+use super::{
+    BeginRequest, EndRequest, FcgiError, GetValues, KeyValuePair, KeyValuePairRef, PaddingPolicy,
+    ProtocolStatus, Record, RecordType, RoleType,
+};
+
+pub fn verify() -> Result<(), FcgiError> {
+    verify_begin_request()?;
+    verify_end_request()?;
+    verify_key_value_pairs()?;
+    verify_get_values()?;
+    verify_records()?;
+
+    Ok(())
+}
+
+fn check(ok: bool, what: &str) -> Result<(), FcgiError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(FcgiError::Protocol(format!("{} did not round-trip", what)))
+    }
+}
+
+fn verify_begin_request() -> Result<(), FcgiError> {
+    for role in [RoleType::Responder, RoleType::Authorizer, RoleType::Filter] {
+        let original = BeginRequest::new(role, 1, [0; 5]);
+        let parsed = BeginRequest::from_bytes(&original.to_vec_u8()?)?;
+
+        check(parsed == original, "BeginRequest")?;
+    }
+
+    Ok(())
+}
+
+fn verify_end_request() -> Result<(), FcgiError> {
+    let statuses = [
+        ProtocolStatus::RequestComplete,
+        ProtocolStatus::CantMpxConn,
+        ProtocolStatus::Overloaded,
+        ProtocolStatus::UnknownRole,
+    ];
+
+    for status in statuses {
+        let original = EndRequest::new(0x01020304, status);
+        let parsed = EndRequest::from_bytes(&original.to_vec_u8())?;
+
+        check(parsed == original, "EndRequest")?;
+    }
+
+    Ok(())
+}
+
+fn verify_key_value_pairs() -> Result<(), FcgiError> {
+    // Sizes either side of the 1-byte/4-byte length boundary.
+    for size in [0, 1, 127, 128, 255, 256, 70000] {
+        let name = "N".repeat(size.max(1));
+        let value = "v".repeat(size);
+        let original = KeyValuePair::new(name, value);
+        let bytes = original.to_vec_u8()?;
+        let (parsed, used) = KeyValuePair::from_bytes(&bytes)?;
+
+        check(parsed == original && used == bytes.len(), "KeyValuePair")?;
+
+        let borrowed = KeyValuePairRef::new(original.name(), original.value());
+        check(borrowed.to_vec_u8()? == bytes, "KeyValuePairRef")?;
+        check(borrowed.encoded_len() == bytes.len(), "KeyValuePairRef length")?;
+    }
+
+    Ok(())
+}
+
+fn verify_get_values() -> Result<(), FcgiError> {
+    let names = vec![
+        String::from("FCGI_MAX_CONNS"),
+        String::from("FCGI_MAX_REQS"),
+        String::from("FCGI_MPXS_CONNS"),
+    ];
+    let original = GetValues::new(names);
+    let parsed = GetValues::from_bytes(&original.to_vec_u8()?)?;
+
+    check(parsed == original, "GetValues")
+}
+
+// Whole records, padding included, parsed back from their own bytes with
+// a trailing byte that mustn't be consumed; cutting one short must fail.
+fn verify_records() -> Result<(), FcgiError> {
+    for padding in [PaddingPolicy::None, PaddingPolicy::Align8, PaddingPolicy::Fixed(3)] {
+        let original = Record::record_with_padding(RecordType::Stdout, b"hello".to_vec(), padding)?;
+        let mut bytes = original.to_vec_u8();
+        let len = bytes.len();
+        bytes.push(0xFF);
+
+        let (parsed, used) = Record::from_bytes(&bytes)?;
+        check(parsed == original && used == len, "Record")?;
+        check(Record::from_bytes(&bytes[..len - 1]).is_err(), "truncated Record")?;
+    }
+
+    // Align8 pads the record as a whole, header included, to a multiple of
+    // 8 bytes: 3 bytes of content take 5 of padding.
+    let aligned = Record::record_aligned(RecordType::Stdout, b"abc".to_vec())?;
+    check(aligned.to_vec_u8().len() % 8 == 0, "aligned Record length")?;
+    check(aligned.header_bytes()[6] == 5, "aligned Record padding")?;
+
+    // The largest content a record can carry, and one byte more.
+    let max = usize::from(u16::MAX);
+    let largest = Record::record_from_data(RecordType::Stdout, vec![0; max], 0)?;
+    check(largest.header_bytes()[4..6] == [0xFF, 0xFF], "u16::MAX content length")?;
+    let (parsed, _) = Record::from_bytes(&largest.to_vec_u8())?;
+    check(parsed.content().len() == max, "u16::MAX content Record")?;
+    check(
+        Record::record_from_data(RecordType::Stdout, vec![0; max + 1], 0).is_err(),
+        "over-long Record rejected",
+    )?;
+
+    Ok(())
+}
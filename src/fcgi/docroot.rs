@@ -0,0 +1,22 @@
+// Mapping a request path onto a file under a document root, for
+// SCRIPT_FILENAME. Both sides are canonicalized before comparing, so
+// `..` segments and symlinks that lead outside the root are refused
+// rather than handed to the app (a local file inclusion hole otherwise).
+
+use std::path::{Path, PathBuf};
+
+// None if the file doesn't exist or resolves outside `doc_root`.
+pub fn resolve_script_path(doc_root: &Path, request_path: &str) -> Option<PathBuf> {
+    let root = doc_root.canonicalize().ok()?;
+    let relative = request_path.trim_start_matches('/');
+    if relative.contains('\0') {
+        return None;
+    }
+
+    let resolved = root.join(relative).canonicalize().ok()?;
+    if resolved.starts_with(&root) {
+        Some(resolved)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,298 @@
+// Builds a Responder-style request in the only order the spec allows:
+//
+//     BeginRequest -> Params* -> empty Params -> Stdin* -> empty Stdin
+//
+// Each stage is its own type, so skipping a step (say, going straight
+// from params to finish without closing the Params stream) doesn't
+// compile:
+//
+//     let bytes = RequestEncoder::new()
+//         .begin(&begin)?
+//         .param("REQUEST_METHOD", "GET")?
+//         .params_done()?
+//         .stdin(b"")?
+//         .finish()?;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{BeginRequest, FcgiError, KeyValuePairRef, PaddingPolicy, Record, RecordType, RoleType};
+
+#[derive(Debug, Default)]
+pub struct RequestEncoder {
+    buf: Vec<u8>,
+}
+
+impl RequestEncoder {
+    pub fn new() -> RequestEncoder {
+        RequestEncoder { buf: Vec::new() }
+    }
+
+    pub fn begin(mut self, begin: &BeginRequest) -> Result<ParamsEncoder, FcgiError> {
+        push_record(&mut self.buf, RecordType::BeginRequest, begin.to_vec_u8()?)?;
+
+        Ok(ParamsEncoder { buf: self.buf })
+    }
+}
+
+#[derive(Debug)]
+pub struct ParamsEncoder {
+    buf: Vec<u8>,
+}
+
+impl ParamsEncoder {
+    // A pair too long for one record is split across several.
+    pub fn param(mut self, name: &str, value: &str) -> Result<ParamsEncoder, FcgiError> {
+        let data = KeyValuePairRef::new(name, value).to_vec_u8()?;
+        for record in Record::split_stream(RecordType::Params, &data, PaddingPolicy::None)? {
+            self.buf.extend(record.to_vec_u8());
+        }
+
+        Ok(self)
+    }
+
+    // Closes the Params stream with its empty record.
+    pub fn params_done(mut self) -> Result<StdinEncoder, FcgiError> {
+        push_record(&mut self.buf, RecordType::Params, Vec::new())?;
+
+        Ok(StdinEncoder {
+            buf: self.buf,
+            has_stdin: false,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct StdinEncoder {
+    buf: Vec<u8>,
+    // Whether any Stdin record has been added; omit_stdin needs none.
+    has_stdin: bool,
+}
+
+impl StdinEncoder {
+    // May be called repeatedly; each call's data is split into records of
+    // at most u16::MAX bytes. Empty data adds nothing.
+    pub fn stdin(mut self, data: &[u8]) -> Result<StdinEncoder, FcgiError> {
+        for chunk in data.chunks(u16::MAX.into()) {
+            push_record(&mut self.buf, RecordType::Stdin, chunk.to_vec())?;
+            self.has_stdin = true;
+        }
+
+        Ok(self)
+    }
+
+    // A request with no body: the Stdin stream is present but empty, i.e.
+    // just its closing record. This is what nginx sends for GET and HEAD,
+    // and what php-fpm expects; a script reading php://input then sees
+    // EOF instead of waiting.
+    pub fn no_body(self) -> Result<Vec<u8>, FcgiError> {
+        self.finish()
+    }
+
+    // A request with no Stdin stream at all, not even the closing record,
+    // for apps that start on the end of Params (Authorizers, per the
+    // spec). Fails if body data was already added.
+    pub fn omit_stdin(self) -> Result<Vec<u8>, FcgiError> {
+        if self.has_stdin {
+            let msg = "Stdin records were already added";
+            return Err(FcgiError::Protocol(String::from(msg)));
+        }
+
+        Ok(self.buf)
+    }
+
+    // Request head (BeginRequest and Params) for handing to a StdinSink.
+    #[cfg(feature = "std")]
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    // Closes the Stdin stream and returns the complete request.
+    pub fn finish(mut self) -> Result<Vec<u8>, FcgiError> {
+        push_record(&mut self.buf, RecordType::Stdin, Vec::new())?;
+
+        Ok(self.buf)
+    }
+}
+
+fn push_record(
+    buf: &mut Vec<u8>,
+    record_type: RecordType,
+    content: Vec<u8>,
+) -> Result<(), FcgiError> {
+    let record = Record::record_from_data(record_type, content, 0)?;
+    buf.extend(record.to_vec_u8());
+
+    Ok(())
+}
+
+// The same request, gathered in any order and put in the right one by
+// build(). The result can be handed straight to Server::send_request:
+//
+//     let bytes = RequestBuilder::new()
+//         .param("REQUEST_METHOD", "POST")
+//         .stdin(body)
+//         .build()?;
+//
+// Authorizers get no Stdin stream, as with Server::execute. Filters get
+// the Data stream after Stdin, and FCGI_DATA_LENGTH if it wasn't set.
+#[derive(Debug)]
+pub struct RequestBuilder {
+    role: RoleType,
+    flags: u8,
+    request_id: u16,
+    padding: PaddingPolicy,
+    max_record_len: u16,
+    params: Vec<(String, String)>,
+    stdin: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Default for RequestBuilder {
+    fn default() -> RequestBuilder {
+        RequestBuilder::new()
+    }
+}
+
+impl RequestBuilder {
+    pub fn new() -> RequestBuilder {
+        RequestBuilder {
+            role: RoleType::Responder,
+            flags: 0,
+            request_id: 1,
+            padding: PaddingPolicy::None,
+            max_record_len: u16::MAX,
+            params: Vec::new(),
+            stdin: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn role(mut self, role: RoleType) -> RequestBuilder {
+        self.role = role;
+        self
+    }
+
+    // BeginRequest flags, e.g. FCGI_KEEP_CONN.
+    pub fn flags(mut self, flags: u8) -> RequestBuilder {
+        self.flags = flags;
+        self
+    }
+
+    // 1 unless several requests share the connection; see Multiplexer.
+    pub fn request_id(mut self, request_id: u16) -> RequestBuilder {
+        self.request_id = request_id;
+        self
+    }
+
+    // Padding for every record, BeginRequest included.
+    pub fn padding(mut self, padding: PaddingPolicy) -> RequestBuilder {
+        self.padding = padding;
+        self
+    }
+
+    // Largest content put in one stream record, as
+    // Server::set_max_record_len. 0 is taken as 1.
+    pub fn max_record_len(mut self, max_len: u16) -> RequestBuilder {
+        self.max_record_len = max_len.max(1);
+        self
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> RequestBuilder {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn params(mut self, params: Vec<(String, String)>) -> RequestBuilder {
+        self.params.extend(params);
+        self
+    }
+
+    pub fn stdin(mut self, stdin: Vec<u8>) -> RequestBuilder {
+        self.stdin = stdin;
+        self
+    }
+
+    // The file to be filtered; only Filters take one.
+    pub fn data(mut self, data: Vec<u8>) -> RequestBuilder {
+        self.data = data;
+        self
+    }
+
+    pub fn build(mut self) -> Result<Vec<u8>, FcgiError> {
+        if self.role == RoleType::Authorizer && !self.stdin.is_empty() {
+            return Err(FcgiError::Protocol(String::from(
+                "Authorizer requests cannot carry a body",
+            )));
+        }
+        if !self.data.is_empty() {
+            check_data_allowed_for(self.role)?;
+        }
+        let has_length = self.params.iter().any(|(k, _)| k == "FCGI_DATA_LENGTH");
+        if self.role == RoleType::Filter && !has_length {
+            let length = self.data.len().to_string();
+            self.params.push((String::from("FCGI_DATA_LENGTH"), length));
+        }
+
+        let mut out = Vec::new();
+        let begin = BeginRequest::new(self.role, self.flags, [0; 5]).to_vec_u8()?;
+        self.push_record(&mut out, RecordType::BeginRequest, begin)?;
+
+        let mut params = Vec::new();
+        for (name, value) in &self.params {
+            KeyValuePairRef::new(name, value).write_into(&mut params)?;
+        }
+        self.push_stream(&mut out, RecordType::Params, &params)?;
+
+        if self.role != RoleType::Authorizer {
+            self.push_stream(&mut out, RecordType::Stdin, &self.stdin)?;
+        }
+        if self.role == RoleType::Filter {
+            self.push_stream(&mut out, RecordType::Data, &self.data)?;
+        }
+
+        Ok(out)
+    }
+
+    // A whole stream: the data in records of at most max_record_len bytes,
+    // then the empty record closing it.
+    fn push_stream(
+        &self,
+        buf: &mut Vec<u8>,
+        record_type: RecordType,
+        data: &[u8],
+    ) -> Result<(), FcgiError> {
+        for chunk in data.chunks(self.max_record_len.into()) {
+            self.push_record(buf, record_type, chunk.to_vec())?;
+        }
+
+        self.push_record(buf, record_type, Vec::new())
+    }
+
+    fn push_record(
+        &self,
+        buf: &mut Vec<u8>,
+        record_type: RecordType,
+        content: Vec<u8>,
+    ) -> Result<(), FcgiError> {
+        let padding = self.padding.padding_length(content.len());
+        let record = Record::record_for_request(record_type, content, padding, self.request_id)?;
+        buf.extend(record.to_vec_u8());
+
+        Ok(())
+    }
+}
+
+// Only a Filter takes a Data stream.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S6.4
+pub(crate) fn check_data_allowed_for(role: RoleType) -> Result<(), FcgiError> {
+    if role != RoleType::Filter {
+        return Err(FcgiError::Protocol(format!(
+            "Data streams are only sent to filters, not {:?}",
+            role
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use super::ParsedRecordType;
+#[cfg(feature = "std")]
+use super::FcgiResponse;
+
+#[derive(Debug)]
+pub enum FcgiError {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Protocol(String),
+    // Record content over the u16::MAX bytes a record can carry.
+    ContentTooLong,
+    // A name or value over the 2^31 - 1 bytes a length prefix can express.
+    NameOrValueTooLarge,
+    // A param name that can't be a CGI variable; see KeyValuePair::new_checked.
+    InvalidParamName(String),
+    // The value of the named param contains a NUL.
+    InvalidParamValue(String),
+    // The named param is in both sets given to Params::merge with
+    // MergeStrategy::Error.
+    DuplicateParam(String),
+    // The socket file doesn't exist, usually because the app isn't running.
+    #[cfg(feature = "std")]
+    SocketNotFound(PathBuf),
+    // The socket exists but we may not connect to it (owner/group/mode).
+    #[cfg(feature = "std")]
+    SocketPermissionDenied(PathBuf),
+    // Nothing is listening at the address: the socket file is left over
+    // from an app that has exited, or no app has the TCP port open.
+    #[cfg(feature = "std")]
+    ConnectionRefused(String),
+    // The connection failed part way through a response. Whatever stdout
+    // and stderr arrived before the failure is kept in `partial`, which is
+    // usually enough to see how far a crashing script got.
+    #[cfg(feature = "std")]
+    Incomplete {
+        partial: FcgiResponse,
+        source: io::Error,
+    },
+    // The params exceed the limits set on the Server, so the request was
+    // never sent. Apps such as php-fpm drop requests whose params overflow
+    // their buffers, usually with no useful error.
+    ParamsTooLarge {
+        pairs: usize,
+        bytes: usize,
+    },
+    // The response went past the limits set on the Server, in content
+    // bytes or in records, and was abandoned. The counts are how far
+    // reading got.
+    ResponseTooLarge {
+        bytes: usize,
+        records: usize,
+    },
+    // The app answered with FCGI_UNKNOWN_TYPE: it doesn't understand the
+    // management record of this type that we sent, e.g. an old backend
+    // without GetValues support.
+    UnknownManagementType(u8),
+    // A record that has no place in a response, e.g. a GetValuesResult
+    // in the middle of one or a type byte the spec doesn't define, with
+    // the content length it declared. Whatever follows it can't be trusted
+    // to belong to the response.
+    UnexpectedRecord {
+        record_type: ParsedRecordType,
+        length: u16,
+    },
+    // A record for another request than the one being read, e.g. left
+    // over from an aborted request on a kept-alive connection; see
+    // Server::set_strict_request_ids.
+    RequestIdMismatch {
+        expected: u16,
+        got: u16,
+    },
+    // An inbound record's version byte isn't FCGI_VERSION_1: the stream
+    // is out of step, or the peer doesn't speak FastCGI at all.
+    UnsupportedVersion(u8),
+    // Every connection a ServerPool may open is in use.
+    #[cfg(feature = "std")]
+    PoolExhausted,
+}
+
+impl fmt::Display for FcgiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            FcgiError::Io(e) => write!(f, "I/O error: {}", e),
+            FcgiError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            FcgiError::ContentTooLong => write!(f, "record content too long"),
+            FcgiError::NameOrValueTooLarge => write!(f, "param name or value too large"),
+            FcgiError::InvalidParamName(name) => write!(f, "invalid param name {:?}", name),
+            FcgiError::InvalidParamValue(name) => write!(f, "param {} has a NUL in its value", name),
+            FcgiError::DuplicateParam(name) => write!(f, "param {} is already set", name),
+            #[cfg(feature = "std")]
+            FcgiError::SocketNotFound(path) => {
+                write!(f, "socket {} not found; is the FastCGI app running?", path.display())
+            }
+            #[cfg(feature = "std")]
+            FcgiError::SocketPermissionDenied(path) => {
+                write!(f, "permission denied connecting to socket {}", path.display())
+            }
+            #[cfg(feature = "std")]
+            FcgiError::ConnectionRefused(addr) => {
+                write!(f, "connection to {} refused; is the FastCGI app listening?", addr)
+            }
+            #[cfg(feature = "std")]
+            FcgiError::Incomplete { partial, source } => write!(
+                f,
+                "response incomplete after {} stdout and {} stderr bytes: {}",
+                partial.stdout.len(),
+                partial.stderr.len(),
+                source
+            ),
+            FcgiError::ParamsTooLarge { pairs, bytes } => {
+                write!(f, "params too large: {} pairs, {} bytes", pairs, bytes)
+            }
+            FcgiError::ResponseTooLarge { bytes, records } => {
+                write!(f, "response too large: {} bytes in {} records", bytes, records)
+            }
+            FcgiError::RequestIdMismatch { expected, got } => {
+                write!(f, "record for request id {} while reading request id {}", got, expected)
+            }
+            FcgiError::UnsupportedVersion(version) => {
+                write!(f, "unsupported FastCGI version {} in record header", version)
+            }
+            FcgiError::UnknownManagementType(record_type) => {
+                write!(f, "app does not understand management record type {}", record_type)
+            }
+            FcgiError::UnexpectedRecord {
+                record_type,
+                length,
+            } => {
+                let record_type = record_type.describe();
+                write!(f, "unexpected {} of {} bytes in a response", record_type, length)
+            }
+            #[cfg(feature = "std")]
+            FcgiError::PoolExhausted => write!(f, "connection pool exhausted"),
+        }
+    }
+}
+
+impl core::error::Error for FcgiError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            FcgiError::Io(e) => Some(e),
+            #[cfg(feature = "std")]
+            FcgiError::Incomplete { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// Protocol errors found while reading travel as the payload of an
+// io::Error through code that can only return one, and are unpacked
+// again here.
+#[cfg(feature = "std")]
+impl From<io::Error> for FcgiError {
+    fn from(e: io::Error) -> FcgiError {
+        if !carries_fcgi_error(&e) {
+            return FcgiError::Io(e);
+        }
+
+        match e.into_inner().map(|inner| inner.downcast::<FcgiError>()) {
+            Some(Ok(inner)) => *inner,
+            _ => unreachable!("checked by carries_fcgi_error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl FcgiError {
+    pub(crate) fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn carries_fcgi_error(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<FcgiError>())
+}
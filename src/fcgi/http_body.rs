@@ -0,0 +1,95 @@
+// The response as an http_body::Body, so it can be handed to hyper as a
+// Response body without being buffered. Each non-empty Stdout record
+// becomes one data frame; EndRequest becomes a trailers frame carrying
+// fcgi-app-status and fcgi-protocol-status, after which the body ends.
+// Stderr is kept aside and can be read with stderr(), and the EndRequest
+// itself with end_request() once the trailers have been sent.
+//
+// The underlying reads block. Poll the body from a blocking-friendly
+// context (tokio's spawn_blocking, a dedicated thread) rather than
+// directly on an async executor.
+
+use std::io::{Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue};
+use http_body::{Body, Frame};
+
+use super::{EndRequest, FcgiError, RecordType, Server};
+
+pub struct FcgiBody<C: Read + Write> {
+    server: Server<C>,
+    stderr: Vec<u8>,
+    // Set once EndRequest has been turned into the trailers frame.
+    end: Option<EndRequest>,
+}
+
+impl<C: Read + Write> FcgiBody<C> {
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr
+    }
+
+    pub fn end_request(&self) -> Option<&EndRequest> {
+        self.end.as_ref()
+    }
+
+    // Gives the connection back, e.g. to reuse it once the body is done.
+    pub fn into_server(self) -> Server<C> {
+        self.server
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Frame<Bytes>>, FcgiError> {
+        while self.end.is_none() {
+            let (record_type, record_body) = self.server.read_app_record()?;
+
+            if record_type == RecordType::Stdout as u8 && !record_body.is_empty() {
+                return Ok(Some(Frame::data(Bytes::from(record_body))));
+            } else if record_type == RecordType::Stderr as u8 {
+                self.stderr.extend(record_body);
+            } else if record_type == RecordType::EndRequest as u8 {
+                let end = EndRequest::from_bytes(&record_body)?;
+
+                let mut trailers = HeaderMap::new();
+                trailers.insert("fcgi-app-status", HeaderValue::from(end.app_status()));
+                trailers.insert(
+                    "fcgi-protocol-status",
+                    HeaderValue::from(end.protocol_status() as u16),
+                );
+                self.end = Some(end);
+                return Ok(Some(Frame::trailers(trailers)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<C: Read + Write + Unpin> Body for FcgiBody<C> {
+    type Data = Bytes;
+    type Error = FcgiError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, FcgiError>>> {
+        Poll::Ready(self.get_mut().next_frame().transpose())
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.end.is_some()
+    }
+}
+
+impl<C: Read + Write> Server<C> {
+    // The response to a request already sent on this connection, as a
+    // Body. Header parsing is left to the caller.
+    pub fn into_http_body(self) -> FcgiBody<C> {
+        FcgiBody {
+            server: self,
+            stderr: Vec::new(),
+            end: None,
+        }
+    }
+}
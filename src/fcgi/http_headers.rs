@@ -0,0 +1,30 @@
+// HTTP request headers from an http::HeaderMap, as hyper hands them over,
+// turned into CGI variables. A header sent more than once becomes one
+// variable with the values joined by ", ", as RFC 3875 asks; Cookie is
+// joined with "; " instead, the separator cookie parsers expect.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-4.1.18
+
+use http::HeaderMap;
+
+use super::params::header_separator;
+use super::ParamsBuilder;
+
+impl ParamsBuilder {
+    // Adds every header in `map` the way http_header does, so Host also
+    // sets SERVER_NAME and Content-Type/Content-Length keep their own
+    // names. Values that aren't valid UTF-8 are converted lossily.
+    pub fn headers(mut self, map: &HeaderMap) -> ParamsBuilder {
+        for name in map.keys() {
+            let separator = header_separator(name.as_str());
+            let values: Vec<_> = map
+                .get_all(name)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                .collect();
+
+            self = self.http_header(name.as_str(), &values.join(separator));
+        }
+
+        self
+    }
+}
@@ -0,0 +1,131 @@
+// A scripted stand-in for a FastCGI app, for exercising request and
+// response handling without a socket. The records it will answer with
+// are queued up front, all for request id 1; everything the client
+// writes is kept and can be looked at afterwards:
+//
+//     let backend = MockBackend::new()
+//         .stderr(b"notice")
+//         .stdout(b"Content-Type: text/plain\r\n\r\nhi")
+//         .stdout(b"")
+//         .end(0);
+//     let mut server = Server::from_stream(params, backend);
+//     let response = server.execute(b"")?;
+//     let sent = server.get_ref().sent();
+//
+// Reads hand back the queued bytes as they are asked for, or in pieces
+// with max_read, and then report EOF, as a connection closed by the app
+// would.
+
+use std::io::{Cursor, Error, Read, Write};
+
+use super::server::StreamControl;
+use super::{EndRequest, FcgiError, ProtocolStatus, Record, RecordType};
+
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    reply: Cursor<Vec<u8>>,
+    sent: Vec<u8>,
+    // Most bytes one read hands back; None for as many as are asked for.
+    max_read: Option<usize>,
+}
+
+impl MockBackend {
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    // One Stdout record; empty data gives the record closing the stream.
+    // Data over u16::MAX bytes is split across records.
+    pub fn stdout(self, data: &[u8]) -> MockBackend {
+        self.stream(RecordType::Stdout, data)
+    }
+
+    pub fn stderr(self, data: &[u8]) -> MockBackend {
+        self.stream(RecordType::Stderr, data)
+    }
+
+    // EndRequest with REQUEST_COMPLETE and this exit code.
+    pub fn end(self, app_status: u32) -> MockBackend {
+        self.end_with(app_status, ProtocolStatus::RequestComplete)
+    }
+
+    pub fn end_with(self, app_status: u32, protocol_status: ProtocolStatus) -> MockBackend {
+        let body = EndRequest::new(app_status, protocol_status).to_vec_u8();
+        self.push(RecordType::EndRequest, body)
+    }
+
+    // Any record as is, e.g. one for another request id or a management
+    // record.
+    pub fn record(self, record: Record) -> MockBackend {
+        self.raw(&record.to_vec_u8())
+    }
+
+    // Bytes that needn't be records at all, for testing malformed input.
+    pub fn raw(mut self, bytes: &[u8]) -> MockBackend {
+        self.reply.get_mut().extend_from_slice(bytes);
+        self
+    }
+
+    // Hands back at most `max` bytes per read, as a socket delivering the
+    // reply in small segments would; 1 splits every header and body
+    // across as many reads as it has bytes.
+    pub fn max_read(mut self, max: usize) -> MockBackend {
+        self.max_read = Some(max.max(1));
+        self
+    }
+
+    // Everything the client has written so far.
+    pub fn sent(&self) -> &[u8] {
+        &self.sent
+    }
+
+    // The client's writes parsed back into records.
+    pub fn sent_records(&self) -> Result<Vec<Record>, FcgiError> {
+        let mut records = Vec::new();
+        let mut rest = &self.sent[..];
+        while !rest.is_empty() {
+            let (record, used) = Record::from_bytes(rest)?;
+            records.push(record);
+            rest = &rest[used..];
+        }
+
+        Ok(records)
+    }
+
+    fn stream(mut self, record_type: RecordType, data: &[u8]) -> MockBackend {
+        if data.is_empty() {
+            return self.push(record_type, Vec::new());
+        }
+        for chunk in data.chunks(u16::MAX.into()) {
+            self = self.push(record_type, chunk.to_vec());
+        }
+
+        self
+    }
+
+    // `content` is never over u16::MAX bytes here.
+    fn push(self, record_type: RecordType, content: Vec<u8>) -> MockBackend {
+        let record = Record::record_from_data(record_type, content, 0).expect("content fits");
+        self.record(record)
+    }
+}
+
+impl Read for MockBackend {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.max_read.map_or(buf.len(), |max| max.min(buf.len()));
+        self.reply.read(&mut buf[..len])
+    }
+}
+
+impl Write for MockBackend {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.sent.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl StreamControl for MockBackend {}
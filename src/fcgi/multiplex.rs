@@ -0,0 +1,212 @@
+// Several requests in flight on one connection at once, told apart by
+// their request ids. Only for apps that report FCGI_MPXS_CONNS as 1
+// (Server::supports_multiplexing); most, php-fpm included, handle one
+// request per connection and must be given a connection each.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S3.3
+//
+// Each request is sent in full by begin. Responses are read as poll asks
+// for them: every record read goes to the buffer of the request it
+// belongs to, whichever request was polled for. The server's response
+// limits (Server::set_response_limits) apply to each response on its
+// own.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+use super::server::{check_response_type, check_unknown_type};
+use super::{
+    EndRequest, FcgiError, FcgiResponse, RecordType, RequestBuilder, Server, FCGI_NULL_REQUEST_ID,
+    KEEP_CONN,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(u16);
+
+impl RequestId {
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    end: Option<EndRequest>,
+    // Content bytes and records read so far, and whether they went past
+    // the response limits; if so nothing more is kept and poll fails.
+    bytes: usize,
+    records: usize,
+    too_large: bool,
+}
+
+pub struct Multiplexer<C: Read + Write> {
+    server: Server<C>,
+    pending: HashMap<u16, Pending>,
+    // Requests whose responses went past the limits and were given up on
+    // before their EndRequest. Their remaining records are dropped as they
+    // arrive, and the id is free again once the EndRequest has been read.
+    abandoned: HashSet<u16>,
+    // Where the search for a free request id starts.
+    next_id: u16,
+}
+
+impl<C: Read + Write> Multiplexer<C> {
+    // The server's stored params and role are used for every request.
+    pub fn new(server: Server<C>) -> Multiplexer<C> {
+        Multiplexer {
+            server,
+            pending: HashMap::new(),
+            abandoned: HashSet::new(),
+            next_id: 1,
+        }
+    }
+
+    // Sends a complete request, the stored params followed by `params`
+    // and `body` as stdin, under a request id no other request in flight
+    // has. KEEP_CONN is always set, since the app may not close a
+    // connection other requests still use.
+    pub fn begin(&mut self, params: Vec<(String, String)>, body: &[u8]) -> Result<RequestId, FcgiError> {
+        let id = self.free_id()?;
+        let stored = self
+            .server
+            .stored_params()
+            .iter()
+            .map(|kv| (kv.name().to_string(), kv.value().to_string()))
+            .collect();
+
+        let bytes = RequestBuilder::new()
+            .role(self.server.role())
+            .flags(KEEP_CONN)
+            .request_id(id)
+            .params(stored)
+            .params(params)
+            .stdin(body.to_vec())
+            .build()?;
+        self.server.send_request(bytes)?;
+        self.server.flush()?;
+
+        self.pending.insert(id, Pending::default());
+        self.next_id = id.checked_add(1).unwrap_or(1);
+
+        Ok(RequestId(id))
+    }
+
+    // The response to `id` once its EndRequest has been read, and None
+    // while it hasn't; ResponseTooLarge once it has gone past the response
+    // limits, after which its remaining records are dropped as they come
+    // in. Reads at most one record. After an error such as
+    // WouldBlock on a non-blocking socket nothing is lost; poll again.
+    pub fn poll(&mut self, id: RequestId) -> Result<Option<FcgiResponse>, FcgiError> {
+        let done = match self.pending.get(&id.0) {
+            Some(pending) => pending.end.is_some() || pending.too_large,
+            None => return Err(unknown_request(id.0)),
+        };
+        if !done {
+            self.read_one()?;
+        }
+
+        self.take_finished(id.0)
+    }
+
+    // Polls until the response to `id` is complete.
+    pub fn wait(&mut self, id: RequestId) -> Result<FcgiResponse, FcgiError> {
+        loop {
+            if let Some(response) = self.poll(id)? {
+                return Ok(response);
+            }
+        }
+    }
+
+    // As Server::set_response_limits, for each response.
+    pub fn set_response_limits(&mut self, max_bytes: usize, max_records: usize) {
+        self.server.set_response_limits(max_bytes, max_records);
+    }
+
+    // Requests begun whose responses haven't been handed out yet.
+    pub fn in_flight(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Gives the connection back once nothing is in flight.
+    pub fn into_server(self) -> Server<C> {
+        self.server
+    }
+
+    fn read_one(&mut self) -> Result<(), FcgiError> {
+        let (_, record_type, request_id, record_body) = self.server.read_raw_record()?;
+        check_unknown_type(record_type, &record_body)?;
+        if request_id == FCGI_NULL_REQUEST_ID {
+            return Ok(());
+        }
+        check_response_type(record_type, &record_body)?;
+
+        if self.abandoned.contains(&request_id) {
+            if record_type == RecordType::EndRequest as u8 {
+                self.abandoned.remove(&request_id);
+            }
+            return Ok(());
+        }
+        let pending = match self.pending.get_mut(&request_id) {
+            Some(pending) if pending.end.is_none() => pending,
+            _ => return Err(unknown_request(request_id)),
+        };
+        pending.bytes += record_body.len();
+        pending.records += 1;
+        let (max_bytes, max_records) = self.server.response_limits();
+        pending.too_large |= pending.bytes > max_bytes || pending.records > max_records;
+
+        if record_type == RecordType::EndRequest as u8 {
+            pending.end = Some(EndRequest::from_bytes(&record_body)?);
+        } else if pending.too_large {
+            pending.stdout = Vec::new();
+            pending.stderr = Vec::new();
+        } else if record_type == RecordType::Stdout as u8 {
+            pending.stdout.extend(record_body);
+        } else if record_type == RecordType::Stderr as u8 {
+            pending.stderr.extend(record_body);
+        }
+
+        Ok(())
+    }
+
+    // The response to `id` if it is complete, or ResponseTooLarge if it
+    // went past the limits; either way the request is no longer pending.
+    fn take_finished(&mut self, id: u16) -> Result<Option<FcgiResponse>, FcgiError> {
+        let finished = self.pending.get(&id).is_some_and(|p| p.too_large || p.end.is_some());
+        if !finished {
+            return Ok(None);
+        }
+
+        let pending = self.pending.remove(&id).unwrap_or_default();
+        let end = match pending.end {
+            Some(end) if !pending.too_large => end,
+            end => {
+                if end.is_none() {
+                    self.abandoned.insert(id);
+                }
+                let (bytes, records) = (pending.bytes, pending.records);
+                return Err(FcgiError::ResponseTooLarge { bytes, records });
+            }
+        };
+        let mut response = FcgiResponse::new(pending.stdout, pending.stderr);
+        response.app_status = end.app_status();
+        response.protocol_status = end.protocol_status();
+        response.end_request = Some(end);
+
+        Ok(Some(response))
+    }
+
+    // The first id from next_id on that no request in flight uses; 0 is
+    // reserved for management records.
+    fn free_id(&self) -> Result<u16, FcgiError> {
+        (self.next_id..=u16::MAX)
+            .chain(1..self.next_id)
+            .find(|id| !self.pending.contains_key(id) && !self.abandoned.contains(id))
+            .ok_or_else(|| FcgiError::Protocol(String::from("no free request id")))
+    }
+}
+
+fn unknown_request(id: u16) -> FcgiError {
+    FcgiError::Protocol(format!("Record for request id {}, which is not in flight", id))
+}
@@ -0,0 +1,307 @@
+// Helpers for building the CGI environment sent in the Params stream.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-4.1.18
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::net::SocketAddr;
+
+use super::FcgiError;
+
+// Turn an HTTP header name into its CGI meta-variable name.
+// Content-Type and Content-Length have dedicated variables and are
+// not HTTP_ prefixed; PHP reads POST bodies using exactly those names.
+pub fn cgi_header_name(http_name: &str) -> String {
+    let upper = http_name.trim().to_ascii_uppercase().replace('-', "_");
+
+    match upper.as_str() {
+        "CONTENT_TYPE" | "CONTENT_LENGTH" => upper,
+        _ => format!("HTTP_{}", upper),
+    }
+}
+
+// The connection-derived variables, taken from the client (`peer`) and
+// our own (`local`) end of the HTTP connection. No reverse lookup is done,
+// so REMOTE_HOST is the address, as RFC 3875 allows.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-4.1.9
+pub fn cgi_params_from_conn(peer: SocketAddr, local: SocketAddr) -> Vec<(String, String)> {
+    let remote_addr = peer.ip().to_string();
+    vec![
+        (String::from("REMOTE_ADDR"), remote_addr.clone()),
+        (String::from("REMOTE_HOST"), remote_addr),
+        (String::from("REMOTE_PORT"), peer.port().to_string()),
+        (String::from("SERVER_ADDR"), local.ip().to_string()),
+        (String::from("SERVER_PORT"), local.port().to_string()),
+    ]
+}
+
+// The variables derived from an HTTP Host header: HTTP_HOST with the value
+// as sent, SERVER_NAME with the port stripped, and SERVER_PORT when the
+// header names one. Name-based virtual hosting, PHP's included, keys off
+// SERVER_NAME. IPv6 literals keep their brackets.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-4.1.14
+pub fn cgi_params_from_host(host: &str) -> Vec<(String, String)> {
+    let host = host.trim();
+    let (name, port) = split_host_port(host);
+
+    let mut params = vec![
+        (String::from("HTTP_HOST"), host.to_string()),
+        (String::from("SERVER_NAME"), name.to_string()),
+    ];
+    if let Some(port) = port {
+        params.push((String::from("SERVER_PORT"), port.to_string()));
+    }
+
+    params
+}
+
+// What a Filter is told about its Data stream: when the file being
+// filtered was last modified, in seconds since the epoch, and its length.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S6.4
+pub fn filter_data_params(last_mod: u64, length: usize) -> Vec<(String, String)> {
+    vec![
+        (String::from("FCGI_DATA_LAST_MOD"), last_mod.to_string()),
+        (String::from("FCGI_DATA_LENGTH"), length.to_string()),
+    ]
+}
+
+// The variables that are the same for every request, as in
+// Params::cgi_defaults, with this crate as SERVER_SOFTWARE and no
+// SERVER_ADDR. Extend them with the request's own, e.g. through
+// merge_params.
+pub fn default_params() -> Vec<(String, String)> {
+    vec![
+        (String::from("GATEWAY_INTERFACE"), String::from("CGI/1.1")),
+        (String::from("SERVER_PROTOCOL"), String::from("HTTP/1.1")),
+        (String::from("SERVER_SOFTWARE"), String::from(SERVER_SOFTWARE)),
+        (String::from("REDIRECT_STATUS"), String::from("200")),
+    ]
+}
+
+// `base` with `overrides` applied in order: a name already present takes
+// the later value where it first appeared, a new one is added at the end.
+pub fn merge_params(
+    base: Vec<(String, String)>,
+    overrides: Vec<(String, String)>,
+) -> Vec<(String, String)> {
+    let mut params = Params { pairs: base };
+    for (name, value) in overrides {
+        params.set(&name, &value);
+    }
+
+    params.into_vec()
+}
+
+const SERVER_SOFTWARE: &str = concat!("rsfcgi/", env!("CARGO_PKG_VERSION"));
+
+// The CGI environment for one HTTP request. QUERY_STRING is whatever
+// follows the first '?' in `uri`, empty if there is none, and SCRIPT_NAME
+// is the path before it. Every header becomes a variable as http_header
+// makes it; a header that appears more than once is sent once, with its
+// values joined by ", " (Cookie by "; "), matching ParamsBuilder::headers.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-4.1
+pub fn params_from_http(
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    remote_addr: &str,
+    script_filename: &str,
+) -> Vec<(String, String)> {
+    let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+
+    let mut folded: Vec<(&str, String)> = Vec::new();
+    for (name, value) in headers {
+        match folded.iter_mut().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            Some((n, joined)) => {
+                joined.push_str(header_separator(n));
+                joined.push_str(value);
+            }
+            None => folded.push((name, value.to_string())),
+        }
+    }
+
+    let mut builder = ParamsBuilder::new()
+        .param("REQUEST_METHOD", method)
+        .param("REQUEST_URI", uri)
+        .param("QUERY_STRING", query)
+        .param("SCRIPT_NAME", path)
+        .param("SCRIPT_FILENAME", script_filename)
+        .param("REMOTE_ADDR", remote_addr);
+    for (name, value) in &folded {
+        builder = builder.http_header(name, value);
+    }
+
+    builder.build()
+}
+
+// What goes between the values of a repeated header when they are folded
+// into one variable.
+pub(crate) fn header_separator(name: &str) -> &'static str {
+    if name.trim().eq_ignore_ascii_case("cookie") {
+        "; "
+    } else {
+        ", "
+    }
+}
+
+fn split_host_port(host: &str) -> (&str, Option<&str>) {
+    let name_end = if host.starts_with('[') {
+        match host.find(']') {
+            Some(i) => i + 1,
+            None => return (host, None),
+        }
+    } else {
+        host.find(':').unwrap_or(host.len())
+    };
+
+    let (name, rest) = host.split_at(name_end);
+    let port = rest
+        .strip_prefix(':')
+        .filter(|port| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()));
+
+    (name, port)
+}
+
+// How Params::merge treats a name that both sets define.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    // The incoming value replaces the existing one, keeping its position.
+    Override,
+    KeepExisting,
+    Error,
+}
+
+// An ordered set of CGI variables. Setting a name that is already present
+// replaces its value in place rather than adding a duplicate.
+#[derive(Debug, Default, Clone)]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params { pairs: Vec::new() }
+    }
+
+    // The variables that don't depend on the request. Callers add the
+    // request-specific ones (REQUEST_METHOD, SCRIPT_FILENAME, ...) on top.
+    // Some apps refuse to run without GATEWAY_INTERFACE. php-cgi and
+    // php-fpm built with force-cgi-redirect answer "Access denied" unless
+    // REDIRECT_STATUS is set, proving the request came through a server;
+    // override it with set if the app wants another value.
+    //
+    // SERVER_PROTOCOL defaults to HTTP/1.1 whatever the client spoke. PHP
+    // hands it to scripts unchanged as $_SERVER['SERVER_PROTOCOL'], and
+    // scripts commonly echo it into a status line or compare it with
+    // HTTP/1.0 and HTTP/1.1, so HTTP/2.0 or HTTP/3 can confuse them.
+    // nginx passes the real version; set it explicitly to do the same.
+    pub fn cgi_defaults(server_addr: &str, server_software: &str) -> Params {
+        let mut params = Params {
+            pairs: default_params(),
+        };
+        params.set("SERVER_SOFTWARE", server_software);
+        params.set("SERVER_ADDR", server_addr);
+
+        params
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.pairs.iter_mut().find(|(k, _)| k == name) {
+            Some(pair) => pair.1 = value.to_string(),
+            None => self.pairs.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // Adds `other`'s variables after this set's own. Names present in both
+    // are resolved by `strategy`; with MergeStrategy::Error nothing is
+    // changed if any name collides.
+    pub fn merge(&mut self, other: Params, strategy: MergeStrategy) -> Result<(), FcgiError> {
+        if strategy == MergeStrategy::Error {
+            if let Some((name, _)) = other.pairs.iter().find(|(k, _)| self.get(k).is_some()) {
+                return Err(FcgiError::DuplicateParam(name.clone()));
+            }
+        }
+
+        for (name, value) in other.pairs {
+            if strategy == MergeStrategy::KeepExisting && self.get(&name).is_some() {
+                continue;
+            }
+            self.set(&name, &value);
+        }
+
+        Ok(())
+    }
+
+    // Sets HTTP_HOST, SERVER_NAME and, if present, SERVER_PORT from a
+    // Host header; see cgi_params_from_host.
+    pub fn set_host(&mut self, host: &str) {
+        for (name, value) in cgi_params_from_host(host) {
+            self.set(&name, &value);
+        }
+    }
+
+    // Sets FCGI_DATA_LAST_MOD and FCGI_DATA_LENGTH; see filter_data_params.
+    pub fn set_filter_data(&mut self, last_mod: u64, length: usize) {
+        for (name, value) in filter_data_params(last_mod, length) {
+            self.set(&name, &value);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, (String, String)> {
+        self.pairs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<(String, String)> {
+        self.pairs
+    }
+}
+
+impl From<Params> for Vec<(String, String)> {
+    fn from(params: Params) -> Vec<(String, String)> {
+        params.pairs
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ParamsBuilder {
+    params: Vec<(String, String)>,
+}
+
+impl ParamsBuilder {
+    pub fn new() -> ParamsBuilder {
+        ParamsBuilder { params: Vec::new() }
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> ParamsBuilder {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Host also fills in SERVER_NAME and SERVER_PORT.
+    pub fn http_header(mut self, name: &str, value: &str) -> ParamsBuilder {
+        if name.trim().eq_ignore_ascii_case("host") {
+            self.params.extend(cgi_params_from_host(value));
+            return self;
+        }
+
+        let cgi_name = cgi_header_name(name);
+        self.param(&cgi_name, value)
+    }
+
+    pub fn build(self) -> Vec<(String, String)> {
+        self.params
+    }
+}
@@ -0,0 +1,210 @@
+// Reusable keep-alive connections to one backend. Connections come from
+// a ServerBuilder (with KEEP_CONN switched on) and are handed out as
+// PooledServer guards, which put the connection back into the pool when
+// dropped. A guard dropped part way through a response first reads the
+// rest of it, so the next user doesn't get the leftovers; call discard
+// instead to close the connection without waiting for it.
+//
+// Apps close connections that sit idle for too long (php-fpm's
+// pm.process_idle_timeout), so with a max_idle set, connections idle for
+// longer than that are dropped instead of handed out; a fresh one is
+// opened in their place.
+//
+// With a max_size set, at most that many connections are open at once,
+// idle and handed out together. Size it to the backend: php-fpm serves
+// one connection per worker, so going past pm.max_children only queues
+// connections in its listen backlog. Once the cap is reached acquire
+// waits for a guard to be dropped, and try_acquire fails with
+// FcgiError::PoolExhausted.
+
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::server::StreamControl;
+use super::{Connect, FcgiError, Server, ServerBuilder, KEEP_CONN};
+
+pub struct ServerPool<C: Read + Write> {
+    builder: ServerBuilder<C>,
+    state: Mutex<PoolState<C>>,
+    // Signalled whenever a connection is returned or closed.
+    available: Condvar,
+    max_idle: Option<Duration>,
+    max_size: Option<usize>,
+}
+
+struct PoolState<C: Read + Write> {
+    idle: Vec<IdleServer<C>>,
+    // Connections open right now, idle ones included.
+    open: usize,
+}
+
+struct IdleServer<C: Read + Write> {
+    server: Server<C>,
+    since: Instant,
+}
+
+impl<C: Read + Write + StreamControl + Connect> ServerPool<C> {
+    pub fn new(builder: ServerBuilder<C>) -> ServerPool<C> {
+        ServerPool {
+            builder: builder.keep_alive(true),
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            available: Condvar::new(),
+            max_idle: None,
+            max_size: None,
+        }
+    }
+
+    pub fn max_idle(mut self, max_idle: Duration) -> ServerPool<C> {
+        self.max_idle = Some(max_idle);
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> ServerPool<C> {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    // The most recently returned connection that hasn't been idle too
+    // long, or a new one. Blocks while the pool is at max_size.
+    pub fn acquire(&self) -> Result<PooledServer<'_, C>, FcgiError> {
+        self.acquire_with(true)
+    }
+
+    // Like acquire, failing with PoolExhausted instead of waiting.
+    pub fn try_acquire(&self) -> Result<PooledServer<'_, C>, FcgiError> {
+        self.acquire_with(false)
+    }
+
+    fn acquire_with(&self, block: bool) -> Result<PooledServer<'_, C>, FcgiError> {
+        let mut state = self.lock();
+        loop {
+            while let Some(entry) = state.idle.pop() {
+                let stale = self.max_idle.is_some_and(|max| entry.since.elapsed() > max);
+                if !stale {
+                    return Ok(self.guard(entry.server));
+                }
+                state.open -= 1;
+            }
+
+            if self.max_size.is_none_or(|max| state.open < max) {
+                break;
+            }
+            if !block {
+                return Err(FcgiError::PoolExhausted);
+            }
+            state = self.available.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        // Count the connection before opening it, so other threads see
+        // the slot as taken, and give the slot back if the connect fails.
+        state.open += 1;
+        drop(state);
+
+        match self.builder.connect() {
+            Ok(server) => Ok(self.guard(server)),
+            Err(e) => {
+                self.close_one();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn idle_count(&self) -> usize {
+        self.lock().idle.len()
+    }
+
+    // Connections open right now, both idle and handed out.
+    pub fn open_count(&self) -> usize {
+        self.lock().open
+    }
+}
+
+impl<C: Read + Write> ServerPool<C> {
+    fn lock(&self) -> MutexGuard<'_, PoolState<C>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn guard(&self, server: Server<C>) -> PooledServer<'_, C> {
+        PooledServer {
+            pool: self,
+            server: Some(server),
+        }
+    }
+
+    fn release(&self, server: Server<C>) {
+        self.lock().idle.push(IdleServer {
+            server,
+            since: Instant::now(),
+        });
+        self.available.notify_one();
+    }
+
+    fn close_one(&self) {
+        self.lock().open -= 1;
+        self.available.notify_one();
+    }
+}
+
+// A connection on loan from a ServerPool. It derefs to the Server; when
+// dropped the connection goes back to the pool if it still asks the app
+// for KEEP_CONN, and is closed otherwise.
+pub struct PooledServer<'a, C: Read + Write> {
+    pool: &'a ServerPool<C>,
+    // Only None while being dropped or discarded.
+    server: Option<Server<C>>,
+}
+
+impl<'a, C: Read + Write> PooledServer<'a, C> {
+    // Closes the connection instead of returning it, e.g. after an error
+    // part way through a response.
+    pub fn discard(mut self) {
+        self.server = None;
+        self.pool.close_one();
+    }
+}
+
+impl<'a, C: Read + Write> Deref for PooledServer<'a, C> {
+    type Target = Server<C>;
+
+    fn deref(&self) -> &Server<C> {
+        self.server.as_ref().expect("connection already returned")
+    }
+}
+
+impl<'a, C: Read + Write> DerefMut for PooledServer<'a, C> {
+    fn deref_mut(&mut self) -> &mut Server<C> {
+        self.server.as_mut().expect("connection already returned")
+    }
+}
+
+impl<'a, C: Read + Write> Drop for PooledServer<'a, C> {
+    fn drop(&mut self) {
+        let mut server = match self.server.take() {
+            Some(server) => server,
+            None => return,
+        };
+
+        // The app only keeps the connection open if the last request
+        // asked it to, whatever the server is set to now.
+        let keep_conn = match server.last_begin_request() {
+            Some(begin) => begin.keep_conn(),
+            None => server.request_flags() & KEEP_CONN != 0,
+        };
+        // The rest of a response left unread would be taken as the next
+        // user's, so read it off first; a connection that can't be
+        // drained is closed instead.
+        let reusable = keep_conn
+            && (!server.request_in_flight() || server.drain_until_end_request().is_ok());
+        if reusable {
+            self.pool.release(server);
+        } else {
+            drop(server);
+            self.pool.close_one();
+        }
+    }
+}
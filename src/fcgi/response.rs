@@ -0,0 +1,218 @@
+// The application's reply, split into its streams, with the CGI header
+// block parsed off the front of stdout.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-6
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{EndRequest, ProtocolStatus, FCGI_VERSION_1};
+
+// Records of each type are appended in the order they arrive, so stderr
+// emitted before (or between) stdout records still ends up in the right
+// buffer.
+#[derive(Debug, Default)]
+pub struct FcgiResponse {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub headers: Vec<(String, String)>,
+    pub http_status: u16,
+    // 1 once a record was read, else 0. Records of any other version
+    // than FCGI_VERSION_1 are refused with FcgiError::UnsupportedVersion.
+    pub protocol_version: u8,
+    // The app's exit code and protocol status, once EndRequest was read.
+    // app_status and protocol_status are copied out of it, and keep their
+    // defaults (0 and RequestComplete) until then.
+    pub end_request: Option<EndRequest>,
+    pub app_status: u32,
+    pub protocol_status: ProtocolStatus,
+    body_start: usize,
+}
+
+impl FcgiResponse {
+    pub fn new(stdout: Vec<u8>, stderr: Vec<u8>) -> FcgiResponse {
+        let mut response = FcgiResponse {
+            stdout,
+            stderr,
+            protocol_version: FCGI_VERSION_1,
+            ..FcgiResponse::default()
+        };
+        response.parse_headers();
+
+        response
+    }
+
+    // Splits the header block off stdout. Runs over the whole accumulated
+    // buffer, so header lines that straddle record boundaries are fine.
+    pub(crate) fn parse_headers(&mut self) {
+        self.headers.clear();
+        self.body_start = 0;
+        self.http_status = 200;
+
+        let (header_end, body_start) = match find_header_end(&self.stdout) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        let block = String::from_utf8_lossy(&self.stdout[..header_end]);
+        for line in block.lines() {
+            if let Some((name, value)) = line.split_once(':') {
+                self.headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        self.body_start = body_start;
+
+        self.http_status = status_from_headers(self.header("Status"), self.header("Location"));
+    }
+
+    // First value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // Headers as they appeared, duplicates included.
+    pub fn iter(&self) -> HeaderIter<'_> {
+        HeaderIter {
+            inner: self.headers.iter(),
+        }
+    }
+
+    // Every value of a repeated header such as Set-Cookie, in order.
+    // Duplicates are never folded together when parsing.
+    pub fn headers_all(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    // The app finished the request itself and exited with status 0.
+    pub fn is_success(&self) -> bool {
+        self.protocol_status == ProtocolStatus::RequestComplete && self.app_status == 0
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.stdout[self.body_start..]
+    }
+
+    // The header block at the front of stdout, blank line included.
+    #[cfg(feature = "std")]
+    pub(crate) fn header_block(&self) -> &[u8] {
+        &self.stdout[..self.body_start]
+    }
+
+    // The charset parameter of Content-Type, unquoted, if there is one.
+    pub fn charset(&self) -> Option<&str> {
+        self.header("Content-Type")?
+            .split(';')
+            .skip(1)
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    }
+
+    // The body decoded as the Content-Type charset says: Latin-1 byte for
+    // byte, anything else (UTF-8, no charset, or one we don't know) as
+    // UTF-8 with bad sequences replaced by U+FFFD.
+    pub fn body_as_string(&self) -> String {
+        let latin1 = ["iso-8859-1", "iso8859-1", "latin1", "l1"];
+        match self.charset() {
+            Some(charset) if latin1.iter().any(|l| charset.eq_ignore_ascii_case(l)) => {
+                self.body().iter().map(|&b| char::from(b)).collect()
+            }
+            _ => String::from_utf8_lossy(self.body()).into_owned(),
+        }
+    }
+
+    // Stderr as log lines, trimmed, with blank lines dropped. Apps like
+    // php-fpm write one entry per line. A line that isn't valid UTF-8 is
+    // cut short at the first bad byte rather than skipped.
+    pub fn stderr_lines(&self) -> impl Iterator<Item = &str> {
+        self.stderr
+            .split(|&b| b == b'\n')
+            .map(|line| match core::str::from_utf8(line) {
+                Ok(line) => line,
+                Err(e) => core::str::from_utf8(&line[..e.valid_up_to()]).unwrap_or_default(),
+            })
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+    }
+
+    // php-fpm's reply when SCRIPT_FILENAME doesn't point at a file: the
+    // body "File not found." with a 404, and "Primary script unknown" on
+    // stderr. Almost always a doc root or path mapping mistake.
+    pub fn is_primary_script_unknown(&self) -> bool {
+        let logged = self.stderr_lines().any(|line| line.contains("Primary script unknown"));
+        let not_found = self.http_status == 404 && self.body().trim_ascii() == b"File not found.";
+
+        logged || not_found
+    }
+
+    // Takes the response apart without copying the body.
+    pub fn into_parts(mut self) -> (u16, Vec<(String, String)>, Vec<u8>) {
+        let body = self.stdout.split_off(self.body_start);
+        (self.http_status, self.headers, body)
+    }
+}
+
+pub struct HeaderIter<'a> {
+    inner: core::slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for HeaderIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.next().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+// `for (name, value) in &response` walks the parsed headers.
+impl<'a> IntoIterator for &'a FcgiResponse {
+    type Item = (&'a str, &'a str);
+    type IntoIter = HeaderIter<'a>;
+
+    fn into_iter(self) -> HeaderIter<'a> {
+        self.iter()
+    }
+}
+
+// The status line a proxy should send: the code from Status, else 302
+// when only Location is given, else 200. A Status that doesn't start with
+// a three-digit code is the app's fault, so it becomes 502 Bad Gateway.
+// https://datatracker.ietf.org/doc/html/rfc3875#section-6.3.3
+fn status_from_headers(status: Option<&str>, location: Option<&str>) -> u16 {
+    match status {
+        Some(status) => status
+            .split_whitespace()
+            .next()
+            .and_then(|c| c.parse::<u16>().ok())
+            .filter(|code| (100..=999).contains(code))
+            .unwrap_or(502),
+        None if location.is_some() => 302,
+        None => 200,
+    }
+}
+
+// Position of the blank line ending the header block: the end of the
+// headers and the start of the body. Accepts both CRLF and bare LF.
+fn find_header_end(buf: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    while i < buf.len() {
+        if buf[i..].starts_with(b"\r\n\r\n") {
+            return Some((i, i + 4));
+        }
+        if buf[i..].starts_with(b"\n\n") {
+            return Some((i, i + 2));
+        }
+        if buf[i..].starts_with(b"\n\r\n") {
+            return Some((i, i + 3));
+        }
+        i += 1;
+    }
+
+    None
+}
@@ -0,0 +1,1824 @@
+#[cfg(unix)]
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::collections::HashMap;
+#[cfg(all(windows, feature = "windows-pipe"))]
+use std::fs::File;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::io::Write;
+use std::io::Read;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::BufWriter;
+use std::io::IoSlice;
+#[cfg(unix)]
+use std::sync::mpsc;
+#[cfg(unix)]
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::encoder::check_data_allowed_for;
+use super::error::carries_fcgi_error;
+use super::{
+    BeginRequest, EndRequest, FcgiError, FcgiResponse, GetValues, HeaderView, KeyValuePair,
+    PaddingPolicy, Params, ParsedRecordType, Record, RecordType, RequestStats, RoleType, StdinSink,
+    FCGI_NULL_REQUEST_ID, FCGI_VERSION_1, KEEP_CONN,
+};
+
+// Meta types
+
+// An Authorizer's verdict. A 200 means the request may go ahead, and the
+// Variable-* headers (prefix stripped) are meant to be passed on as params
+// to the Responder that serves it. Any other status is a denial, and the
+// response is what to send the client instead.
+#[derive(Debug)]
+pub struct AuthResult {
+    pub status: u16,
+    pub variables: HashMap<String, String>,
+    pub response: FcgiResponse,
+}
+
+impl AuthResult {
+    pub fn is_authorized(&self) -> bool {
+        self.status == 200
+    }
+}
+
+pub enum DownstreamConnection {
+    #[cfg(unix)]
+    UnixSocket(UnixStream),
+    TcpSocket(TcpStream),
+    #[cfg(all(windows, feature = "windows-pipe"))]
+    NamedPipe(File),
+}
+
+pub enum ConcreteServer {
+    #[cfg(unix)]
+    UnixServer(Server<UnixStream>),
+    TcpServer(Server<TcpStream>),
+    #[cfg(all(windows, feature = "windows-pipe"))]
+    NamedPipeServer(Server<File>),
+}
+
+pub fn server_from_downstream(params_raw: Vec<(String, String)>, conn: DownstreamConnection) -> ConcreteServer {
+    server_from_downstream_with_role(params_raw, conn, RoleType::Responder)
+}
+
+pub fn server_from_downstream_with_role(
+    params_raw: Vec<(String, String)>,
+    conn: DownstreamConnection,
+    role: RoleType,
+) -> ConcreteServer {
+    match conn {
+        #[cfg(unix)]
+        DownstreamConnection::UnixSocket(unix_stream) => ConcreteServer::UnixServer(Server::with_role(params_raw, unix_stream, role)),
+        DownstreamConnection::TcpSocket(tcp_socket) => ConcreteServer::TcpServer(Server::with_role(params_raw, tcp_socket, role)),
+        #[cfg(all(windows, feature = "windows-pipe"))]
+        DownstreamConnection::NamedPipe(pipe) => ConcreteServer::NamedPipeServer(Server::with_role(params_raw, pipe, role)),
+    }
+}
+
+#[cfg(unix)]
+pub fn server_from_unix_path(params_raw: Vec<(String, String)>, path: String) -> Result<ConcreteServer, FcgiError> {
+    server_from_unix_path_with_role(params_raw, path, RoleType::Responder)
+}
+
+// For single-purpose backends (e.g. an authorizer) the role is fixed
+// for the lifetime of the connection rather than chosen per request.
+#[cfg(unix)]
+pub fn server_from_unix_path_with_role(
+    params_raw: Vec<(String, String)>,
+    path: String,
+    role: RoleType,
+) -> Result<ConcreteServer, FcgiError> {
+    let socket: UnixStream = UnixStream::connect(&path).map_err(|e| connect_error(e, &path))?;
+    Ok(server_from_downstream_with_role(params_raw, DownstreamConnection::UnixSocket(socket), role))
+}
+
+// One Responder request over a fresh connection to the Unix socket at
+// `socket_path`, for scripts and tests that just want the reply: the
+// parsed headers and status, the body, stderr, and the app's exit status.
+// The connection is closed afterwards.
+#[cfg(unix)]
+pub fn fcgi_request(
+    socket_path: &str,
+    params: Vec<(String, String)>,
+    body: &[u8],
+) -> Result<FcgiResponse, FcgiError> {
+    let stream = UnixStream::connect(socket_path).map_err(|e| connect_error(e, socket_path))?;
+
+    Server::from_stream(params, stream).execute(body)
+}
+
+// Like server_from_unix_path, but gives up if the connect doesn't finish
+// within `connect_timeout` (e.g. the app's accept backlog is full).
+#[cfg(unix)]
+pub fn server_from_unix_path_timeout(
+    params_raw: Vec<(String, String)>,
+    path: String,
+    connect_timeout: Duration,
+) -> Result<ConcreteServer, FcgiError> {
+    let socket = connect_unix_timeout(&path, connect_timeout)?;
+    Ok(server_from_downstream(params_raw, DownstreamConnection::UnixSocket(socket)))
+}
+
+// std has no connect-with-timeout for Unix sockets, so the connect runs on
+// its own thread and we stop waiting at the deadline. A connect that does
+// finish late is dropped, closing the socket.
+#[cfg(unix)]
+pub(crate) fn connect_unix_timeout(path: &str, timeout: Duration) -> Result<UnixStream, FcgiError> {
+    let (tx, rx) = mpsc::channel();
+    let target = path.to_string();
+    thread::spawn(move || {
+        let _ = tx.send(UnixStream::connect(target));
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result.map_err(|e| connect_error(e, path)),
+        Err(_) => Err(FcgiError::Io(Error::new(ErrorKind::TimedOut, "connect timed out"))),
+    }
+}
+
+// The response limits a Server starts with: far more than any page, but
+// finite. See Server::set_response_limits.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 1 << 30;
+pub const DEFAULT_MAX_RESPONSE_RECORDS: usize = 1 << 22;
+
+// Accepts anything that resolves to socket addresses: "127.0.0.1:9000",
+// "[::1]:9000", "php:9000" in a container network, or a SocketAddr.
+// Each resolved address is tried in turn; if none connect, the last
+// error is returned.
+pub fn server_from_tcp_addr<A: ToSocketAddrs>(
+    params_raw: Vec<(String, String)>,
+    addr: A,
+) -> Result<ConcreteServer, FcgiError> {
+    let stream = connect_tcp_timeout(addr, None)?;
+    Ok(server_from_downstream(params_raw, DownstreamConnection::TcpSocket(stream)))
+}
+
+// Like server_from_tcp_addr, with `connect_timeout` applied to each
+// resolved address in turn.
+pub fn server_from_tcp_addr_timeout<A: ToSocketAddrs>(
+    params_raw: Vec<(String, String)>,
+    addr: A,
+    connect_timeout: Duration,
+) -> Result<ConcreteServer, FcgiError> {
+    let stream = connect_tcp_timeout(addr, Some(connect_timeout))?;
+    Ok(server_from_downstream(params_raw, DownstreamConnection::TcpSocket(stream)))
+}
+
+// Shorthand for server_from_tcp_addr with a "host:port" string, e.g.
+// php-fpm's usual "127.0.0.1:9000".
+pub fn server_from_tcp(params_raw: Vec<(String, String)>, addr: &str) -> Result<ConcreteServer, FcgiError> {
+    server_from_tcp_addr(params_raw, addr)
+}
+
+// Like server_from_tcp_addr, with `options` applied to the socket before
+// the first request goes out.
+pub fn server_from_tcp_with<A: ToSocketAddrs>(
+    params_raw: Vec<(String, String)>,
+    addr: A,
+    options: TcpOptions,
+) -> Result<ConcreteServer, FcgiError> {
+    let stream = connect_tcp_timeout(addr, None)?;
+    stream.set_tcp_options(&options)?;
+    Ok(server_from_downstream(params_raw, DownstreamConnection::TcpSocket(stream)))
+}
+
+// Socket options for TCP connections. nodelay turns Nagle's algorithm off,
+// so the many small records of a request aren't held back waiting for an
+// ACK. keepalive enables TCP keepalive probes after the connection has
+// been idle that long, so a pooled connection the network silently
+// dropped is noticed. Both are off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TcpOptions {
+    pub nodelay: bool,
+    pub keepalive: Option<Duration>,
+}
+
+impl TcpOptions {
+    pub(crate) fn apply(&self, stream: &TcpStream) -> Result<(), Error> {
+        stream.set_nodelay(self.nodelay)?;
+
+        let socket = socket2::SockRef::from(stream);
+        match self.keepalive {
+            Some(idle) => socket.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(idle)),
+            None => socket.set_keepalive(false),
+        }
+    }
+}
+
+pub(crate) fn connect_tcp_timeout<A: ToSocketAddrs>(
+    addr: A,
+    timeout: Option<Duration>,
+) -> Result<TcpStream, FcgiError> {
+    let nothing = Error::new(ErrorKind::InvalidInput, "address resolved to nothing");
+    let mut last_error = FcgiError::Io(nothing);
+
+    for candidate in addr.to_socket_addrs()? {
+        let attempt = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&candidate, timeout),
+            None => TcpStream::connect(candidate),
+        };
+        match attempt {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = connect_error(e, &candidate.to_string()),
+        }
+    }
+
+    Err(last_error)
+}
+
+// The usual php-fpm setup mistakes, fpm not running (a missing socket
+// file, or nothing listening) and the socket owned by the wrong user, get
+// their own variants so they can be reported with the address that
+// failed. A proxy can answer those with 502 or 503 and log the reason.
+pub(crate) fn connect_error(e: Error, path: &str) -> FcgiError {
+    match e.kind() {
+        ErrorKind::NotFound => FcgiError::SocketNotFound(path.into()),
+        ErrorKind::PermissionDenied => FcgiError::SocketPermissionDenied(path.into()),
+        ErrorKind::ConnectionRefused => FcgiError::ConnectionRefused(path.into()),
+        _ => FcgiError::Io(e),
+    }
+}
+
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S2.2
+// Process managers traditionally hand a FastCGI process its socket on fd 0.
+#[cfg(unix)]
+pub const FCGI_LISTENSOCK_FILENO: RawFd = 0;
+
+/// Builds a server around a connected Unix socket passed down by a
+/// supervisor (systemd, launchd, a pre-forking parent).
+///
+/// # Safety
+///
+/// `fd` must be an open, connected Unix stream socket that nothing else
+/// owns; the returned server takes ownership and closes it on drop.
+#[cfg(unix)]
+pub unsafe fn server_from_raw_fd(
+    params_raw: Vec<(String, String)>,
+    fd: RawFd,
+) -> ConcreteServer {
+    use std::os::unix::io::FromRawFd;
+
+    let socket: UnixStream = UnixStream::from_raw_fd(fd);
+    server_from_downstream(params_raw, DownstreamConnection::UnixSocket(socket))
+}
+
+// Windows FastCGI apps (IIS-style PHP, some .NET hosts) listen on a named
+// pipe. A pipe client handle opened for read and write behaves like any
+// other duplex stream, so it plugs into the generic Server as a File.
+// Accepts either a bare name ("php-fpm") or a full "\\.\pipe\php-fpm".
+#[cfg(all(windows, feature = "windows-pipe"))]
+pub fn server_from_named_pipe(params_raw: Vec<(String, String)>, pipe_name: String) -> Result<ConcreteServer, FcgiError> {
+    let path = if pipe_name.starts_with(r"\\.\pipe\") {
+        pipe_name
+    } else {
+        format!(r"\\.\pipe\{}", pipe_name)
+    };
+
+    let pipe: File = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .map_err(|e| connect_error(e, &path))?;
+    Ok(server_from_downstream(params_raw, DownstreamConnection::NamedPipe(pipe)))
+}
+
+// Socket-level controls that not every transport has. Streams without
+// a half-close or shutdown treat close_write and close as no-ops; streams
+// that can't switch to non-blocking mode report Unsupported.
+pub trait StreamControl {
+    fn close_write(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> Result<(), Error> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> Result<(), Error> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+
+    fn read_timeout(&self) -> Result<Option<Duration>, Error> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> Result<(), Error> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+
+    // Waits up to `wait` for the peer to close the connection, returning
+    // true if it did. Only meant for idle connections: no data is expected,
+    // so any byte that does arrive is reported as an error.
+    fn peer_closed(&mut self, _wait: Duration) -> Result<bool, Error> {
+        Err(Error::from(ErrorKind::Unsupported))
+    }
+
+    // Only means something for TCP; other transports ignore it.
+    fn set_tcp_options(&self, _options: &TcpOptions) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// Shared by the socket transports: a short blocking read that either sees
+// EOF (closed) or times out (still open).
+pub(crate) fn read_for_close<S: Read>(
+    stream: &mut S,
+    get_timeout: impl Fn(&S) -> Result<Option<Duration>, Error>,
+    set_timeout: impl Fn(&S, Option<Duration>) -> Result<(), Error>,
+    wait: Duration,
+) -> Result<bool, Error> {
+    let previous = get_timeout(stream)?;
+    set_timeout(stream, Some(wait))?;
+
+    let mut byte = [0u8; 1];
+    let outcome = match stream.read(&mut byte) {
+        Ok(0) => Ok(true),
+        Ok(_) => Err(Error::new(ErrorKind::InvalidData, "unexpected data on idle connection")),
+        Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => Ok(false),
+        Err(e) if e.kind() == ErrorKind::ConnectionReset => Ok(true),
+        // TLS streams whose peer dropped the socket without close_notify.
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(true),
+        Err(e) => Err(e),
+    };
+
+    set_timeout(stream, previous)?;
+    outcome
+}
+
+#[cfg(unix)]
+impl StreamControl for UnixStream {
+    fn close_write(&mut self) -> Result<(), Error> {
+        self.shutdown(std::net::Shutdown::Write)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.shutdown(std::net::Shutdown::Both)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn read_timeout(&self) -> Result<Option<Duration>, Error> {
+        UnixStream::read_timeout(self)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn peer_closed(&mut self, wait: Duration) -> Result<bool, Error> {
+        read_for_close(self, UnixStream::read_timeout, UnixStream::set_read_timeout, wait)
+    }
+}
+
+impl StreamControl for TcpStream {
+    fn close_write(&mut self) -> Result<(), Error> {
+        self.shutdown(std::net::Shutdown::Write)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.shutdown(std::net::Shutdown::Both)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn read_timeout(&self) -> Result<Option<Duration>, Error> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn peer_closed(&mut self, wait: Duration) -> Result<bool, Error> {
+        read_for_close(self, TcpStream::read_timeout, TcpStream::set_read_timeout, wait)
+    }
+
+    fn set_tcp_options(&self, options: &TcpOptions) -> Result<(), Error> {
+        options.apply(self)
+    }
+}
+
+#[cfg(all(windows, feature = "windows-pipe"))]
+impl StreamControl for File {}
+
+pub struct Server<C: Read + Write> {
+    params: Vec<KeyValuePair>,
+    downstream: C,
+    role: RoleType,
+    padding: PaddingPolicy,
+    // Bytes queued by queue_send that a non-blocking socket hasn't
+    // accepted yet, and how far into them we've got.
+    pending: Vec<u8>,
+    pending_written: usize,
+    // Id used for this connection's requests; see record_from_data.
+    request_id: u16,
+    keep_conn: bool,
+    // Checked before a request is encoded; None means no limit.
+    max_params: Option<usize>,
+    max_params_bytes: Option<usize>,
+    // Whether a record for another request id fails the response (the
+    // default) or is skipped; see set_strict_request_ids.
+    strict_request_ids: bool,
+    // Checked as read_response reads; see set_response_limits.
+    max_response_bytes: usize,
+    max_response_records: usize,
+    // The stored params as Params records, encoded on first use. They
+    // never change, but the padding policy does affect the encoding.
+    params_cache: Option<Vec<u8>>,
+    // Largest content put in one outbound record; see set_max_record_len.
+    max_record_len: u16,
+    // Filled in as execute_timed's request is written and read; see
+    // RequestStats. `request_started` is None outside execute_timed.
+    stats: RequestStats,
+    request_started: Option<Instant>,
+    // Inbound bytes not yet handed out as records: part of a record, or
+    // records read ahead of time; see read_raw_record.
+    inbound: Vec<u8>,
+    // Where the connection was opened, if by ServerBuilder::connect, and
+    // the connect timeout used; what send_request_retrying reconnects to.
+    pub(crate) addr: Option<(String, Option<Duration>)>,
+    // The BeginRequest of the request last sent on this connection.
+    last_begin: Option<BeginRequest>,
+    // A request has been sent and its EndRequest not read yet; see
+    // request_in_flight.
+    in_flight: bool,
+    // Where read_response sends Stderr content instead of buffering it;
+    // see set_on_stderr.
+    on_stderr: Option<StderrHook>,
+}
+
+type StderrHook = Box<dyn FnMut(&[u8]) + Send>;
+
+impl<C: Read + Write> Server<C> {
+    pub fn new(params_raw: Vec<(String, String)>, downstream: C) -> Server<C> {
+        Server::with_role(params_raw, downstream, RoleType::Responder)
+    }
+
+    // Wraps a stream the caller already connected: a TLS session, a
+    // socket handed over by a supervisor or set up with custom options, or
+    // an in-memory mock. No connect step happens here; like the
+    // server_from_* functions, fcgi_request only connects and hands the
+    // stream over.
+    pub fn from_stream(params_raw: Vec<(String, String)>, stream: C) -> Server<C> {
+        Server::new(params_raw, stream)
+    }
+
+    pub fn with_role(params_raw: Vec<(String, String)>, downstream: C, role: RoleType) -> Server<C> {
+        let pair_to_kvp = |p: (String, String)| -> KeyValuePair {
+            let (k, v) = p;
+            KeyValuePair::new(k, v)
+        };
+
+        let params: Vec<KeyValuePair> = params_raw
+                .into_iter().map(pair_to_kvp).collect::<Vec<KeyValuePair>>();
+
+        Server {
+            params,
+            downstream,
+            role,
+            padding: PaddingPolicy::default(),
+            pending: Vec::new(),
+            pending_written: 0,
+            request_id: 1,
+            keep_conn: false,
+            max_params: None,
+            max_params_bytes: None,
+            strict_request_ids: true,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            max_response_records: DEFAULT_MAX_RESPONSE_RECORDS,
+            params_cache: None,
+            max_record_len: u16::MAX,
+            stats: RequestStats::default(),
+            request_started: None,
+            inbound: Vec::new(),
+            addr: None,
+            last_begin: None,
+            in_flight: false,
+            on_stderr: None,
+        }
+    }
+
+    // The connection itself, e.g. a MockBackend to see what was sent.
+    // Reading from or writing to it directly desyncs the records.
+    pub fn get_ref(&self) -> &C {
+        &self.downstream
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.downstream
+    }
+
+    // Whether requests on this connection ask the app to keep it open.
+    pub fn set_keep_conn(&mut self, keep_conn: bool) {
+        self.keep_conn = keep_conn;
+    }
+
+    // BeginRequest flags matching this server's settings.
+    pub fn request_flags(&self) -> u8 {
+        if self.keep_conn {
+            KEEP_CONN
+        } else {
+            0
+        }
+    }
+
+    // Caps the number of params and their total encoded size. Requests
+    // over either limit fail with FcgiError::ParamsTooLarge before
+    // anything is written.
+    pub fn set_params_limits(&mut self, max_pairs: Option<usize>, max_bytes: Option<usize>) {
+        self.max_params = max_pairs;
+        self.max_params_bytes = max_bytes;
+    }
+
+    // Caps how much read_response will take from the app: content bytes
+    // of all records together, and the number of records. Going past
+    // either fails with FcgiError::ResponseTooLarge, so a broken or
+    // hostile backend can't make it buffer without end. A single record
+    // never needs more than 65535 + 255 bytes, whatever its header says;
+    // one that never arrives in full is a job for set_read_timeout.
+    pub fn set_response_limits(&mut self, max_bytes: usize, max_records: usize) {
+        self.max_response_bytes = max_bytes;
+        self.max_response_records = max_records;
+    }
+
+    // The limits set_response_limits set, as (bytes, records).
+    pub fn response_limits(&self) -> (usize, usize) {
+        (self.max_response_bytes, self.max_response_records)
+    }
+
+    // What to do with a record whose request id isn't this server's while
+    // a response is read. Strict, the default, fails with
+    // FcgiError::RequestIdMismatch. Otherwise the record is dropped and
+    // reading goes on, so stale records from an aborted request on a
+    // kept-alive connection don't leak into the next response. Dropped
+    // records still count towards the response limits.
+    pub fn set_strict_request_ids(&mut self, strict: bool) {
+        self.strict_request_ids = strict;
+    }
+
+    // A record to drop rather than fail on; see set_strict_request_ids.
+    fn is_stale(&self, request_id: u16) -> bool {
+        !self.strict_request_ids && request_id != self.request_id
+    }
+
+    pub fn check_params_limits(&self) -> Result<(), FcgiError> {
+        self.check_params_limits_with(&[])
+    }
+
+    // As check_params_limits, counting `extra` per-request params too.
+    fn check_params_limits_with(&self, extra: &[KeyValuePair]) -> Result<(), FcgiError> {
+        let pairs = self.params.len() + extra.len();
+        let bytes: usize = self.params.iter().chain(extra).map(KeyValuePair::encoded_len).sum();
+
+        self.check_params_size(pairs, bytes)
+    }
+
+    fn check_params_size(&self, pairs: usize, bytes: usize) -> Result<(), FcgiError> {
+        let too_many = self.max_params.is_some_and(|max| pairs > max);
+        let too_big = self.max_params_bytes.is_some_and(|max| bytes > max);
+        if too_many || too_big {
+            return Err(FcgiError::ParamsTooLarge { pairs, bytes });
+        }
+
+        Ok(())
+    }
+
+    pub fn role(&self) -> RoleType {
+        self.role
+    }
+
+    // The id this server's requests go out under.
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    // The role and flags actually sent with the last request, which for
+    // authorize differ from role(). None before the first request.
+    pub fn last_begin_request(&self) -> Option<&BeginRequest> {
+        self.last_begin.as_ref()
+    }
+
+    // True from sending a BeginRequest until its EndRequest has been read,
+    // e.g. while a ResponseBody is only part way through the response.
+    pub fn request_in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    // Hands each Stderr record's content to `f` as read_response reads it,
+    // instead of collecting it in FcgiResponse::stderr (or
+    // ResponseBody::stderr), which then stays empty. Most apps write their
+    // error log there, and this gets it to the operator's log rather than
+    // next to the HTTP body:
+    //
+    //     let id = server.request_id();
+    //     server.set_on_stderr(move |line| eprintln!("[fcgi {}] {}", id,
+    //         String::from_utf8_lossy(line).trim_end()));
+    //
+    // The empty record closing the stream is not passed on.
+    pub fn set_on_stderr<F: FnMut(&[u8]) + Send + 'static>(&mut self, f: F) {
+        self.on_stderr = Some(Box::new(f));
+    }
+
+    // Goes back to buffering stderr.
+    pub fn clear_on_stderr(&mut self) {
+        self.on_stderr = None;
+    }
+
+    // Caps the content of each outbound stream record below the protocol
+    // maximum of u16::MAX, e.g. to exercise an app's reassembly of many
+    // small records or to fit an intermediary's buffers. 0 is taken as 1.
+    pub fn set_max_record_len(&mut self, max_len: u16) {
+        self.max_record_len = max_len.max(1);
+        self.params_cache = None;
+    }
+
+    // Padding applied to every record this server builds.
+    pub fn set_padding_policy(&mut self, padding: PaddingPolicy) {
+        self.padding = padding;
+        self.params_cache = None;
+    }
+
+    // BeginRequest record using the role this server was constructed with.
+    pub fn begin_request_record(&self, flags: u8) -> Result<Record, FcgiError> {
+        self.begin_record_as(self.role, flags)
+    }
+
+    fn begin_record_as(&self, role: RoleType, flags: u8) -> Result<Record, FcgiError> {
+        let begin_body = BeginRequest::new(role, flags, [0; 5]);
+        Record::record_with_padding(RecordType::BeginRequest, begin_body.to_vec_u8()?, self.padding)
+    }
+
+    // The stored params as a complete Params stream, closing empty record
+    // included; without it apps such as php-fpm wait for more params.
+    pub fn serialize_params(&self) -> Result<Vec<u8>, FcgiError> {
+        let mut out = self.serialize_pairs(&self.params)?;
+        let terminator = Record::record_with_padding(RecordType::Params, Vec::new(), self.padding)?;
+        out.extend(terminator.to_vec_u8());
+
+        Ok(out)
+    }
+
+    // `body` as a complete Stdin stream: records of at most the record
+    // size limit, then the closing empty record.
+    pub fn serialize_stdin(&self, body: &[u8]) -> Result<Vec<u8>, FcgiError> {
+        self.serialize_stream(RecordType::Stdin, body)
+    }
+
+    // One record per pair, except that a pair too long for one record
+    // (a big cookie header, say) carries on into the next.
+    fn serialize_pairs(&self, pairs: &[KeyValuePair]) -> Result<Vec<u8>, FcgiError> {
+        let mut kv_records: Vec<Vec<u8>> = Vec::new();
+        for kv in pairs.iter() {
+            let data = kv.to_vec_u8()?;
+            for chunk in data.chunks(self.max_record_len.into()) {
+                let chunk = chunk.to_vec();
+                let rec = Record::record_with_padding(RecordType::Params, chunk, self.padding)?;
+                kv_records.push(rec.to_vec_u8());
+            }
+        }
+
+        Ok(kv_records.concat())
+    }
+
+    // Writes the stored params, then `extra`, then the empty record
+    // closing the stream. The stored ones are encoded once per Server.
+    // The params limits are checked by the caller, before BeginRequest.
+    fn write_params_stream(&mut self, extra: &[KeyValuePair]) -> Result<(), FcgiError> {
+        if self.params_cache.is_none() {
+            self.params_cache = Some(self.serialize_pairs(&self.params)?);
+        }
+
+        let mut tail = self.serialize_pairs(extra)?;
+        tail.extend(self.serialize_stream(RecordType::Params, &[])?);
+
+        let cached = self.params_cache.as_deref().unwrap_or_default();
+        self.downstream.write_all(cached)?;
+        self.downstream.write_all(&tail)?;
+        self.stats.bytes_sent += (cached.len() + tail.len()) as u64;
+
+        Ok(())
+    }
+
+    pub fn send_request(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        self.note_begin(&bytes);
+        self.downstream.write_all(&bytes[..])
+    }
+
+    // Marks a request in flight if `bytes` start with a BeginRequest
+    // record for this server's request id.
+    fn note_begin(&mut self, bytes: &[u8]) {
+        if let Some(header) = HeaderView::parse(bytes) {
+            let begin = header.type_byte() == RecordType::BeginRequest as u8;
+            self.in_flight |= begin && header.request_id() == self.request_id;
+        }
+    }
+
+    // Like send_request, for records that haven't been turned into bytes:
+    // each is written through one buffer rather than each getting its own
+    // Vec first.
+    pub fn send_records<I: IntoIterator<Item = Record>>(&mut self, records: I) -> Result<(), Error> {
+        let mut writer = BufWriter::new(&mut self.downstream);
+        for record in records {
+            let begin = record.record_type() == RecordType::BeginRequest;
+            self.in_flight |= begin && record.request_id() == self.request_id;
+            record.write_to(&mut writer)?;
+        }
+
+        writer.flush()
+    }
+
+    // Sends a request body that is held as several borrowed buffers (e.g.
+    // a body assembled from many network reads) as Stdin records, plus
+    // the closing empty record. Records are framed across the logical
+    // concatenation of the chunks and written with vectored writes, so
+    // the body is never copied into one contiguous buffer.
+    pub fn send_stdin_chunks(&mut self, chunks: &[&[u8]]) -> Result<(), FcgiError> {
+        let max_content: usize = self.max_record_len.into();
+        let mut pieces: Vec<&[u8]> = Vec::new();
+        let mut record_len = 0;
+
+        for chunk in chunks {
+            let mut rest: &[u8] = chunk;
+            while !rest.is_empty() {
+                let take = (max_content - record_len).min(rest.len());
+                pieces.push(&rest[..take]);
+                record_len += take;
+                rest = &rest[take..];
+
+                if record_len == max_content {
+                    self.write_record_vectored(RecordType::Stdin, &pieces, record_len)?;
+                    pieces.clear();
+                    record_len = 0;
+                }
+            }
+        }
+
+        if record_len > 0 {
+            self.write_record_vectored(RecordType::Stdin, &pieces, record_len)?;
+        }
+        self.write_record_vectored(RecordType::Stdin, &[], 0)?;
+
+        Ok(())
+    }
+
+    // Writes one record whose content is the concatenation of `pieces`,
+    // which together are `content_length` bytes long.
+    fn write_record_vectored(
+        &mut self,
+        record_type: RecordType,
+        pieces: &[&[u8]],
+        content_length: usize,
+    ) -> Result<(), Error> {
+        let padding_length = self.padding.padding_length(content_length);
+        let [id_hi, id_lo] = self.request_id.to_be_bytes();
+        let [len_hi, len_lo] = (content_length as u16).to_be_bytes();
+        let header = [
+            FCGI_VERSION_1, record_type as u8, id_hi, id_lo, len_hi, len_lo, padding_length, 0,
+        ];
+        let padding = [0u8; 255];
+
+        let mut slices: Vec<IoSlice> = Vec::with_capacity(pieces.len() + 2);
+        slices.push(IoSlice::new(&header));
+        slices.extend(pieces.iter().map(|p| IoSlice::new(p)));
+        slices.push(IoSlice::new(&padding[..padding_length.into()]));
+
+        let mut remaining: &mut [IoSlice] = &mut slices;
+        while !remaining.is_empty() {
+            match self.downstream.write_vectored(remaining) {
+                Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                Ok(n) => IoSlice::advance_slices(&mut remaining, n),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.stats.bytes_sent += (header.len() + content_length + usize::from(padding_length)) as u64;
+
+        Ok(())
+    }
+
+    // Piecewise request sending, so a large body can be streamed to the
+    // app as it becomes available instead of being built into one buffer
+    // first. Call in order: send_begin, send_params, any number of
+    // send_stdin_chunk, then finish_stdin. Each call writes straight to
+    // the connection.
+    pub fn send_begin(&mut self, flags: u8) -> Result<(), FcgiError> {
+        self.send_begin_as(self.role, flags)
+    }
+
+    fn send_begin_as(&mut self, role: RoleType, flags: u8) -> Result<(), FcgiError> {
+        let rec = self.begin_record_as(role, flags)?.to_vec_u8();
+        self.downstream.write_all(&rec)?;
+        self.stats.bytes_sent += rec.len() as u64;
+        self.last_begin = Some(BeginRequest::new(role, flags, [0; 5]));
+        self.in_flight = true;
+
+        Ok(())
+    }
+
+    // The stored params followed by the empty record closing the stream.
+    pub fn send_params(&mut self) -> Result<(), FcgiError> {
+        self.check_params_limits()?;
+        self.write_params_stream(&[])
+    }
+
+    // Chunks over the record size limit are split across several records.
+    pub fn send_stdin_chunk(&mut self, chunk: &[u8]) -> Result<(), FcgiError> {
+        self.check_body_allowed(chunk)?;
+        for piece in chunk.chunks(self.max_record_len.into()) {
+            self.write_record_vectored(RecordType::Stdin, &[piece], piece.len())?;
+        }
+
+        Ok(())
+    }
+
+    // Copies `reader` to the app as Stdin records until it reports EOF,
+    // returning the number of body bytes sent. Like send_stdin_chunk this
+    // leaves the stream open; call finish_stdin afterwards.
+    //
+    // Each record is filled to max_record_len before it is sent, however
+    // little each read returns, so a reader such as a socket doesn't turn
+    // into a stream of tiny records.
+    pub fn send_stdin_from<R: Read>(&mut self, mut reader: R) -> Result<u64, FcgiError> {
+        let mut buf: Vec<u8> = vec![0; self.max_record_len.into()];
+        let mut filled = 0;
+        let mut sent: u64 = 0;
+
+        loop {
+            let n = match reader.read(&mut buf[filled..]) {
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(FcgiError::Io(e)),
+            };
+            filled += n;
+
+            if filled == buf.len() || (n == 0 && filled > 0) {
+                self.send_stdin_chunk(&buf[..filled])?;
+                sent += filled as u64;
+                filled = 0;
+            }
+            if n == 0 {
+                return Ok(sent);
+            }
+        }
+    }
+
+    // Closes Stdin with an empty record. Authorizers have no Stdin stream,
+    // so for them this only flushes.
+    pub fn finish_stdin(&mut self) -> Result<(), FcgiError> {
+        if self.role != RoleType::Authorizer {
+            self.write_record_vectored(RecordType::Stdin, &[], 0)?;
+        }
+        self.downstream.flush()?;
+
+        Ok(())
+    }
+
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S6.4
+    // Filters get the file to filter as a Data stream, sent after Stdin
+    // has been closed. Pass FCGI_DATA_LAST_MOD and FCGI_DATA_LENGTH in the
+    // params so the app knows what to expect.
+    pub fn send_data_chunk(&mut self, chunk: &[u8]) -> Result<(), FcgiError> {
+        self.check_filter()?;
+        for piece in chunk.chunks(self.max_record_len.into()) {
+            self.write_record_vectored(RecordType::Data, &[piece], piece.len())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish_data(&mut self) -> Result<(), FcgiError> {
+        self.check_filter()?;
+        self.write_record_vectored(RecordType::Data, &[], 0)?;
+        self.downstream.flush()?;
+
+        Ok(())
+    }
+
+    fn check_filter(&self) -> Result<(), FcgiError> {
+        check_data_allowed_for(self.role)
+    }
+
+    // A Write handle for the request body on this connection, framing
+    // records with this server's request id, padding and max_record_len.
+    // Send BeginRequest and Params first; finishing the sink closes Stdin.
+    pub fn stdin_sink(&mut self) -> StdinSink<&mut C> {
+        StdinSink::new(&mut self.downstream)
+            .request_id(self.request_id)
+            .padding(self.padding)
+            .max_record_len(self.max_record_len)
+    }
+
+    // Queues bytes for poll_send. Meant for non-blocking sockets driven by
+    // an external event loop, where write_all would fail with WouldBlock
+    // part way through and lose track of what was sent.
+    pub fn queue_send(&mut self, bytes: &[u8]) {
+        self.note_begin(bytes);
+        self.pending.extend_from_slice(bytes);
+    }
+
+    // Writes as much of the queue as the socket will take. Returns true
+    // once everything has been written, false if the socket would block;
+    // call again when it is writable.
+    pub fn poll_send(&mut self) -> Result<bool, Error> {
+        while self.pending_written < self.pending.len() {
+            match self.downstream.write(&self.pending[self.pending_written..]) {
+                Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                Ok(n) => self.pending_written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.pending.clear();
+        self.pending_written = 0;
+        Ok(true)
+    }
+
+    pub fn pending_send_len(&self) -> usize {
+        self.pending.len() - self.pending_written
+    }
+
+    // The exact bytes execute would write for `body`, without touching the
+    // connection; handy for diffing against a capture of another client
+    // talking to the same app.
+    pub fn request_bytes(&self, body: &[u8]) -> Result<Vec<u8>, FcgiError> {
+        self.encode_full_request(self.request_flags(), body)
+    }
+
+    // Every record needed for one complete request: BeginRequest, the
+    // Params stream and the Stdin stream, each closed by an empty record.
+    // Authorizers take no Stdin stream, so the body must be empty for them.
+    fn encode_full_request(&self, flags: u8, body: &[u8]) -> Result<Vec<u8>, FcgiError> {
+        self.check_request(&[], body)?;
+        let mut out: Vec<u8> = Vec::new();
+
+        out.extend(self.begin_request_record(flags)?.to_vec_u8());
+        out.extend(self.serialize_params()?);
+
+        if self.role != RoleType::Authorizer {
+            out.extend(self.serialize_stdin(body)?);
+        }
+        if self.role == RoleType::Filter {
+            out.extend(self.serialize_stream(RecordType::Data, &[])?);
+        }
+
+        Ok(out)
+    }
+
+    // Everything that can be checked before the first byte of a request
+    // with these per-request params and body is written.
+    fn check_request(&self, extra: &[KeyValuePair], body: &[u8]) -> Result<(), FcgiError> {
+        self.check_body_allowed(body)?;
+        self.check_params_limits_with(extra)?;
+        self.check_content_length(extra, body.len())
+    }
+
+    // A CONTENT_LENGTH that disagrees with the body makes PHP either wait
+    // for bytes that never come or cut the body short, so refuse to send
+    // the request at all. The last CONTENT_LENGTH sent is the one that
+    // counts, so per-request params are looked at first.
+    fn check_content_length(&self, extra: &[KeyValuePair], body_len: usize) -> Result<(), FcgiError> {
+        let declared = extra
+            .iter()
+            .rev()
+            .chain(self.params.iter().rev())
+            .find(|kv| kv.name() == "CONTENT_LENGTH");
+
+        check_declared_length(declared, body_len)
+    }
+
+    fn check_body_allowed(&self, body: &[u8]) -> Result<(), FcgiError> {
+        check_body_allowed_for(self.role, body)
+    }
+
+    // One complete request that sends `params` instead of the stored
+    // params, under the role and flags in `begin`, then reads its
+    // response. A proxy can keep one connection, or a pooled one, and
+    // give every request its own environment. As with execute, an
+    // Authorizer gets no Stdin and a Filter an empty Data stream.
+    pub fn request(
+        &mut self,
+        begin: BeginRequest,
+        params: &[(String, String)],
+        stdin: &[u8],
+    ) -> Result<FcgiResponse, FcgiError> {
+        let role = begin.role();
+        let pairs: Vec<KeyValuePair> = params
+            .iter()
+            .map(|(k, v)| KeyValuePair::new(k.clone(), v.clone()))
+            .collect();
+        check_body_allowed_for(role, stdin)?;
+        self.check_params_size(pairs.len(), pairs.iter().map(KeyValuePair::encoded_len).sum())?;
+        let declared = pairs.iter().rev().find(|kv| kv.name() == "CONTENT_LENGTH");
+        check_declared_length(declared, stdin.len())?;
+
+        let mut out = self.serialize_pairs(&pairs)?;
+        out.extend(self.serialize_stream(RecordType::Params, &[])?);
+        if role != RoleType::Authorizer {
+            out.extend(self.serialize_stream(RecordType::Stdin, stdin)?);
+        }
+        if role == RoleType::Filter {
+            out.extend(self.serialize_stream(RecordType::Data, &[])?);
+        }
+
+        self.send_begin_as(role, begin.flags())?;
+        self.downstream.write_all(&out)?;
+        self.downstream.flush()?;
+        self.stats.bytes_sent += out.len() as u64;
+
+        self.read_response()
+    }
+
+    // Splits `data` into records of at most max_record_len bytes followed
+    // by the empty record that closes the stream.
+    fn serialize_stream(&self, record_type: RecordType, data: &[u8]) -> Result<Vec<u8>, FcgiError> {
+        let mut out: Vec<u8> = Vec::new();
+
+        for chunk in data.chunks(self.max_record_len.into()) {
+            let rec = Record::record_with_padding(record_type, chunk.to_vec(), self.padding)?;
+            out.extend(rec.to_vec_u8());
+        }
+
+        let terminator = Record::record_with_padding(record_type, vec![], self.padding)?;
+        out.extend(terminator.to_vec_u8());
+
+        Ok(out)
+    }
+
+    // The response to a request already sent, with stdout and stderr kept
+    // in separate buffers however their records were interleaved.
+    pub fn consume_response(&mut self) -> Result<FcgiResponse, FcgiError> {
+        self.read_response()
+    }
+
+    // consume_response that also hands every record read to `f`, exactly
+    // as it arrived (padding and all) and before it is acted on, up to and
+    // including the EndRequest; e.g. for a debugging proxy logging the
+    // record stream. A record too malformed to parse isn't passed on, and
+    // fails the response as usual.
+    pub fn consume_response_with<F: FnMut(&Record)>(
+        &mut self,
+        mut f: F,
+    ) -> Result<FcgiResponse, FcgiError> {
+        self.read_response_teed(Some(&mut f))
+    }
+
+    // Appends the response's stdout, headers included, to `response` and
+    // returns the EndRequest, whose app_status is the script's exit code:
+    // a PHP fatal error still ends with RequestComplete, but a nonzero
+    // status. Stderr is no longer mixed in; use consume_response for it.
+    //
+    // A connection closed before any record arrived is a clean end rather
+    // than an error, and gives None; closing part way through a response
+    // is still UnexpectedEof.
+    pub fn consume_response_to_string(
+        &mut self,
+        response: &mut String,
+    ) -> Result<Option<EndRequest>, Error> {
+        let reply = match self.consume_response() {
+            Ok(reply) => reply,
+            Err(FcgiError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(FcgiError::Io(e)) | Err(FcgiError::Incomplete { source: e, .. }) => return Err(e),
+            Err(other) => return Err(Error::new(ErrorKind::InvalidData, other)),
+        };
+        // Decoded once over the whole buffer, so a multibyte character
+        // split over two records isn't mangled, and in the charset the
+        // headers declare.
+        response.push_str(&String::from_utf8_lossy(reply.header_block()));
+        response.push_str(&reply.body_as_string());
+
+        Ok(reply.end_request)
+    }
+
+    // One complete request and its response: writes BeginRequest, the
+    // stored params and `body` as stdin, then reads up to EndRequest.
+    pub fn execute(&mut self, body: &[u8]) -> Result<FcgiResponse, FcgiError> {
+        self.check_request(&[], body)?;
+
+        if self.role == RoleType::Filter {
+            return self.execute_filter(body, &[]);
+        }
+
+        self.send_begin(self.request_flags())?;
+        self.write_params_stream(&[])?;
+        self.send_stdin_chunk(body)?;
+        self.finish_stdin()?;
+
+        self.read_response()
+    }
+
+    // Another request on a connection kept open with KEEP_CONN, without
+    // reconnecting. read_response stops right after EndRequest, so the
+    // stream is already positioned for the next BeginRequest; the stats
+    // are reset, apart from connect_ns. Fails if the connection wasn't
+    // kept open, or if part of a record from the last response is still
+    // unread (drain_until_end_request first).
+    pub fn next_request(&mut self, body: &[u8]) -> Result<FcgiResponse, FcgiError> {
+        if !self.keep_conn {
+            return Err(FcgiError::Protocol(String::from(
+                "connection was not kept open; set_keep_conn(true) before the first request",
+            )));
+        }
+        if !self.inbound.is_empty() {
+            return Err(FcgiError::Protocol(String::from(
+                "previous response was not read to the end",
+            )));
+        }
+
+        self.stats = RequestStats {
+            connect_ns: self.stats.connect_ns,
+            ..RequestStats::default()
+        };
+        self.request_started = None;
+
+        self.execute(body)
+    }
+
+    // A complete Filter request: `stdin` is the client's request body and
+    // `data` the file to be filtered. The filtered file comes back on
+    // stdout; reading stops at EndRequest, not when the input streams have
+    // been consumed.
+    pub fn execute_filter(&mut self, stdin: &[u8], data: &[u8]) -> Result<FcgiResponse, FcgiError> {
+        self.check_filter()?;
+        self.check_request(&[], stdin)?;
+
+        self.send_begin(self.request_flags())?;
+        self.write_params_stream(&[])?;
+        self.send_stdin_chunk(stdin)?;
+        self.finish_stdin()?;
+        self.send_data_chunk(data)?;
+        self.finish_data()?;
+
+        self.read_response()
+    }
+
+    // One Authorizer request, whatever role this server was built with:
+    // the stored params and then `params`, and no Stdin stream at all.
+    // Sending Stdin to an Authorizer is a protocol error; the app starts
+    // answering as soon as Params is closed.
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S6.3
+    pub fn authorize(&mut self, params: &Params) -> Result<AuthResult, FcgiError> {
+        let extra: Vec<KeyValuePair> = params
+            .iter()
+            .map(|(k, v)| KeyValuePair::new(k.clone(), v.clone()))
+            .collect();
+        self.check_params_limits_with(&extra)?;
+
+        self.send_begin_as(RoleType::Authorizer, self.request_flags())?;
+        self.write_params_stream(&extra)?;
+        self.downstream.flush()?;
+
+        let response = self.read_response()?;
+        let variables = response
+            .iter()
+            .filter_map(|(name, value)| {
+                let prefix = name.get(..9).filter(|p| p.eq_ignore_ascii_case("Variable-"))?;
+                Some((name[prefix.len()..].to_string(), value.to_string()))
+            })
+            .collect();
+
+        Ok(AuthResult {
+            status: response.http_status,
+            variables,
+            response,
+        })
+    }
+
+    // execute with the body read from `reader` as it is sent, for uploads
+    // too large to hold in memory. The length isn't known up front, so
+    // CONTENT_LENGTH is whatever the stored params say; set it there if
+    // the caller knows it, since PHP won't read a POST body without it.
+    pub fn execute_from<R: Read>(&mut self, reader: R) -> Result<FcgiResponse, FcgiError> {
+        self.check_params_limits()?;
+
+        self.send_begin(self.request_flags())?;
+        self.write_params_stream(&[])?;
+        self.send_stdin_from(reader)?;
+        self.finish_stdin()?;
+        // As with execute, a Filter gets an empty Data stream.
+        if self.role == RoleType::Filter {
+            self.finish_data()?;
+        }
+
+        self.read_response()
+    }
+
+    // execute, also returning timings and byte counts for the request.
+    pub fn execute_timed(&mut self, body: &[u8]) -> Result<(FcgiResponse, RequestStats), FcgiError> {
+        self.stats = RequestStats {
+            connect_ns: self.stats.connect_ns,
+            ..RequestStats::default()
+        };
+        let started = Instant::now();
+        self.request_started = Some(started);
+
+        let result = self.execute(body);
+        self.request_started = None;
+        self.stats.total_ns = elapsed_ns(started);
+
+        Ok((result?, self.stats))
+    }
+
+    // How long opening the connection took, reported as connect_ns.
+    pub(crate) fn set_connect_time(&mut self, connect_time: Duration) {
+        self.stats.connect_ns = connect_time.as_nanos() as u64;
+    }
+
+    // Like execute, with per-request params sent after the stored ones.
+    // Meant for a connection serving the same endpoint over and over: the
+    // stored params are not re-encoded for each call.
+    pub fn request_with_params(&mut self, extra: &Params, body: &[u8]) -> Result<FcgiResponse, FcgiError> {
+        let extra: Vec<KeyValuePair> = extra
+            .iter()
+            .map(|(k, v)| KeyValuePair::new(k.clone(), v.clone()))
+            .collect();
+        self.check_request(&extra, body)?;
+
+        self.send_begin(self.request_flags())?;
+        self.write_params_stream(&extra)?;
+        self.send_stdin_chunk(body)?;
+        self.finish_stdin()?;
+        // As with execute, a Filter gets an empty Data stream.
+        if self.role == RoleType::Filter {
+            self.finish_data()?;
+        }
+
+        self.read_response()
+    }
+
+    pub fn read_response(&mut self) -> Result<FcgiResponse, FcgiError> {
+        self.read_response_teed(None)
+    }
+
+    fn read_response_teed(
+        &mut self,
+        mut tee: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<FcgiResponse, FcgiError> {
+        let mut response = FcgiResponse::default();
+        let mut stdout_closed = false;
+        let mut stderr_closed = false;
+        let mut bytes = 0;
+        let mut records = 0;
+
+        // Stdout and Stderr may be interleaved in any order; only an
+        // EndRequest record finishes the response. An empty record closes
+        // its stream, after which no more content of that type may follow.
+        loop {
+            let read = match tee.as_deref_mut() {
+                Some(f) => self.read_raw_record_teed(f),
+                None => self.read_raw_record(),
+            };
+            let (_, record_type, request_id, record_body) = match read {
+                Ok(r) => r,
+                // No partial response to hand back either when the
+                // connection closed or timed out before a single record, or
+                // on a bad version byte, after which nothing can be trusted.
+                Err(e) if self.nothing_received(&response) || carries_fcgi_error(&e) => {
+                    return Err(FcgiError::from(e))
+                }
+                Err(source) => {
+                    response.parse_headers();
+                    return Err(FcgiError::Incomplete {
+                        partial: response,
+                        source,
+                    })
+                }
+            };
+            bytes += record_body.len();
+            records += 1;
+            if bytes > self.max_response_bytes || records > self.max_response_records {
+                return Err(FcgiError::ResponseTooLarge { bytes, records });
+            }
+
+            check_unknown_type(record_type, &record_body)?;
+            if self.is_stale(request_id) {
+                continue;
+            }
+            check_request_id(request_id, self.request_id)?;
+            check_response_type(record_type, &record_body)?;
+            response.protocol_version = FCGI_VERSION_1;
+
+            if record_type == RecordType::Stdout as u8 {
+                if stdout_closed {
+                    return Err(FcgiError::Protocol(String::from("Stdout record after end of stream")));
+                }
+                stdout_closed = record_body.is_empty();
+                response.stdout.extend(record_body);
+            } else if record_type == RecordType::Stderr as u8 {
+                if stderr_closed {
+                    return Err(FcgiError::Protocol(String::from("Stderr record after end of stream")));
+                }
+                stderr_closed = record_body.is_empty();
+                self.take_stderr(record_body, &mut response.stderr);
+            } else if record_type == RecordType::EndRequest as u8 {
+                let end = EndRequest::from_bytes(&record_body)?;
+                response.app_status = end.app_status();
+                response.protocol_status = end.protocol_status();
+                response.end_request = Some(end);
+                break;
+            }
+        }
+
+        response.parse_headers();
+        Ok(response)
+    }
+
+    // Passes Stderr content to the on_stderr hook, or adds it to `buffer`
+    // when there is none.
+    pub(crate) fn take_stderr(&mut self, content: Vec<u8>, buffer: &mut Vec<u8>) {
+        match self.on_stderr.as_mut() {
+            Some(f) if !content.is_empty() => f(&content),
+            Some(_) => {}
+            None => buffer.extend(content),
+        }
+    }
+
+    // No record of `response` read so far, nor any part of one.
+    fn nothing_received(&self, response: &FcgiResponse) -> bool {
+        response.protocol_version == 0 && self.inbound.is_empty()
+    }
+
+    // Reads and throws away records up to and including the pending
+    // request's EndRequest. Use this before reusing a keep-alive
+    // connection whose last response wasn't read to the end, otherwise its
+    // leftover records would be taken as the next response.
+    pub fn drain_until_end_request(&mut self) -> Result<(), FcgiError> {
+        loop {
+            let (_, record_type, request_id, record_body) = self.read_raw_record()?;
+            check_unknown_type(record_type, &record_body)?;
+            if self.is_stale(request_id) {
+                continue;
+            }
+            check_request_id(request_id, self.request_id)?;
+            if record_type == RecordType::EndRequest as u8 {
+                return Ok(());
+            }
+        }
+    }
+
+    // Asks the app to abandon the request in flight, e.g. after a client
+    // disconnect or a timeout, so it can free what it holds. The app
+    // still answers with an EndRequest, normally with protocolStatus
+    // REQUEST_COMPLETE, so drain up to it (drain_until_end_request)
+    // before reusing the connection; cancel_streaming does both.
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S5.4
+    pub fn abort_request(&mut self) -> Result<(), FcgiError> {
+        self.write_record_vectored(RecordType::AbortRequest, &[], 0)?;
+        self.downstream.flush()?;
+
+        Ok(())
+    }
+
+    // Gives up on a response part way through, e.g. when the HTTP client
+    // behind it has hung up: sends AbortRequest, then reads and discards
+    // whatever the app still sends up to its EndRequest, leaving the
+    // connection ready for the next request. Only call this while the
+    // response is unfinished; after EndRequest it would wait forever.
+    pub fn cancel_streaming(&mut self) -> Result<(), FcgiError> {
+        self.abort_request()?;
+
+        self.drain_until_end_request()
+    }
+
+    // https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S4.1
+    // Asks the app for management variables such as FCGI_MAX_CONNS. The
+    // GetValuesResult stream is reassembled across records before being
+    // decoded, so a reply split mid-pair is still read correctly. Names
+    // the app doesn't know are simply absent from the result.
+    pub fn query_values(&mut self, names: &[&str]) -> Result<Vec<(String, String)>, FcgiError> {
+        let query = GetValues::new(names.iter().map(|n| n.to_string()).collect());
+        let content = query.to_vec_u8()?;
+        let padding = self.padding.padding_length(content.len());
+        let rec =
+            Record::record_for_request(RecordType::GetValues, content, padding, FCGI_NULL_REQUEST_ID)?;
+        self.downstream.write_all(&rec.to_vec_u8())?;
+
+        let mut result: Vec<u8> = Vec::new();
+        loop {
+            let (_, record_type, request_id, record_body) = self.read_raw_record()?;
+            check_unknown_type(record_type, &record_body)?;
+            check_request_id(request_id, FCGI_NULL_REQUEST_ID)?;
+            if record_type != RecordType::GetValuesResult as u8 {
+                return Err(FcgiError::Protocol(format!(
+                    "Expected GetValuesResult, got {}",
+                    ParsedRecordType::from_u8(record_type).describe()
+                )));
+            }
+
+            // An empty record closes the stream outright. Otherwise keep
+            // going while a pair is cut short, or while a full-size record
+            // leaves requested names unanswered.
+            if record_body.is_empty() {
+                break;
+            }
+            let full_record = record_body.len() == u16::MAX as usize;
+            result.extend(record_body);
+
+            if KeyValuePair::pairs_complete(&result) {
+                let pairs = KeyValuePair::parse_all(&result)?;
+                let answered = names.iter().all(|n| pairs.iter().any(|kv| kv.name() == *n));
+                if answered || !full_record {
+                    self.skip_buffered_empty_result()?;
+                    break;
+                }
+            }
+        }
+
+        let pairs = KeyValuePair::parse_all(&result)?;
+        Ok(pairs
+            .into_iter()
+            .map(|kv| (kv.name().to_string(), kv.value().to_string()))
+            .collect())
+    }
+
+    // An app may also close the GetValuesResult stream with an empty record
+    // after the one that answered. If it has already arrived it is taken
+    // here, so the next response doesn't start with it.
+    fn skip_buffered_empty_result(&mut self) -> Result<(), FcgiError> {
+        if let Some(header) = HeaderView::parse(&self.inbound) {
+            let empty_result = header.type_byte() == RecordType::GetValuesResult as u8
+                && header.content_length() == 0;
+            if empty_result && self.inbound.len() >= 8 + header.body_length() {
+                self.read_raw_record()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // query_values as a map, e.g. to check FCGI_MPXS_CONNS before trying
+    // to multiplex.
+    pub fn get_values(&mut self, keys: &[&str]) -> Result<HashMap<String, String>, FcgiError> {
+        Ok(self.query_values(keys)?.into_iter().collect())
+    }
+
+    // Single-name query_values, e.g. get_value("FCGI_MAX_REQS"). None if
+    // the app doesn't report that name.
+    pub fn get_value(&mut self, key: &str) -> Result<Option<String>, FcgiError> {
+        let values = self.query_values(&[key])?;
+
+        Ok(values.into_iter().find(|(k, _)| k == key).map(|(_, v)| v))
+    }
+
+    // Swaps in a fresh connection to the same app, dropping anything
+    // buffered for the old one.
+    pub(crate) fn replace_stream(&mut self, downstream: C) {
+        self.downstream = downstream;
+        self.inbound.clear();
+        self.pending.clear();
+        self.pending_written = 0;
+    }
+
+    pub(crate) fn stored_params(&self) -> &[KeyValuePair] {
+        &self.params
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.downstream.flush()
+    }
+
+    // Whether the app answers FCGI_MPXS_CONNS with 1, i.e. may be sent a
+    // Multiplexer's concurrent requests. php-fpm never does.
+    pub fn supports_multiplexing(&mut self) -> Result<bool, FcgiError> {
+        Ok(self.get_value("FCGI_MPXS_CONNS")?.as_deref() == Some("1"))
+    }
+
+    // Next record for the request in flight, as its type byte and content.
+    pub(crate) fn read_app_record(&mut self) -> Result<(u8, Vec<u8>), FcgiError> {
+        loop {
+            let (_, record_type, request_id, record_body) = self.read_raw_record()?;
+            check_unknown_type(record_type, &record_body)?;
+            if self.is_stale(request_id) {
+                continue;
+            }
+            check_request_id(request_id, self.request_id)?;
+            check_response_type(record_type, &record_body)?;
+
+            return Ok((record_type, record_body));
+        }
+    }
+
+    // Reads one whole record (header, content and padding) and returns
+    // its version byte, type byte, request id and content.
+    //
+    // Bytes are gathered in `inbound` and only taken out once the record
+    // is complete, so any number of short reads is fine, and an error part
+    // way through (WouldBlock on a non-blocking socket, a read timeout)
+    // loses nothing: calling again carries on from the same spot.
+    //
+    // Reads ask for at least READ_AHEAD bytes, so a run of small records
+    // (PHP flushing each echo) costs one read call rather than two per
+    // record. Whatever arrives past the current record stays in `inbound`
+    // for the next call.
+    pub(crate) fn read_raw_record(&mut self) -> Result<(u8, u8, u16, Vec<u8>), Error> {
+        let record = self.read_record_bytes()?;
+
+        Ok(split_record(record))
+    }
+
+    // read_raw_record, first passing the record to `f` if it parses.
+    fn read_raw_record_teed(
+        &mut self,
+        f: &mut dyn FnMut(&Record),
+    ) -> Result<(u8, u8, u16, Vec<u8>), Error> {
+        let record = self.read_record_bytes()?;
+        if let Ok((parsed, _)) = Record::from_bytes(&record) {
+            f(&parsed);
+        }
+
+        Ok(split_record(record))
+    }
+
+    // Next record for the request in flight, parsed whole, padding and
+    // all.
+    pub(crate) fn read_record(&mut self) -> Result<Record, FcgiError> {
+        loop {
+            let bytes = self.read_record_bytes()?;
+            let (record, _) = Record::from_bytes(&bytes)?;
+            check_unknown_type(record.record_type() as u8, record.content())?;
+            if self.is_stale(record.request_id()) {
+                continue;
+            }
+            check_request_id(record.request_id(), self.request_id)?;
+
+            return Ok(record);
+        }
+    }
+
+    // The raw bytes of the next record; see read_raw_record.
+    fn read_record_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let record_len = loop {
+            // The version is checked before trusting the content length,
+            // which in something that isn't a record could be anything.
+            let wanted = match HeaderView::parse(&self.inbound) {
+                Some(header) if header.version() != FCGI_VERSION_1 => {
+                    return Err(FcgiError::UnsupportedVersion(header.version()).into_io());
+                }
+                Some(header) => 8 + header.body_length(),
+                None => 8,
+            };
+            if self.inbound.len() >= wanted {
+                break wanted;
+            }
+
+            let start = self.inbound.len();
+            self.inbound.resize(wanted.max(start + READ_AHEAD), 0);
+            match self.downstream.read(&mut self.inbound[start..]) {
+                Ok(0) => {
+                    self.inbound.truncate(start);
+                    let msg = match start {
+                        0 => "connection closed",
+                        _ => "connection closed mid-record",
+                    };
+                    return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+                }
+                Ok(n) => self.inbound.truncate(start + n),
+                Err(e) => {
+                    self.inbound.truncate(start);
+                    if e.kind() != ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+
+            if start == 0 && !self.inbound.is_empty() {
+                if let Some(started) = self.request_started {
+                    if self.stats.ttfb_ns == 0 {
+                        self.stats.ttfb_ns = elapsed_ns(started);
+                    }
+                }
+            }
+        };
+
+        let rest = self.inbound.split_off(record_len);
+        let record = std::mem::replace(&mut self.inbound, rest);
+        self.stats.bytes_received += record.len() as u64;
+        if let Some(header) = HeaderView::parse(&record) {
+            let end = header.type_byte() == RecordType::EndRequest as u8;
+            self.in_flight &= !(end && header.request_id() == self.request_id);
+        }
+
+        Ok(record)
+    }
+}
+
+fn elapsed_ns(since: Instant) -> u64 {
+    since.elapsed().as_nanos() as u64
+}
+
+// The app's reply to a management record it doesn't know. It can arrive
+// in place of any awaited record, and carries request id 0, so look for
+// it before checking the id.
+pub(crate) fn check_unknown_type(record_type: u8, body: &[u8]) -> Result<(), FcgiError> {
+    if record_type != RecordType::UnknownType as u8 {
+        return Ok(());
+    }
+
+    match body.first() {
+        Some(&unknown) => Err(FcgiError::UnknownManagementType(unknown)),
+        None => Err(FcgiError::Protocol(String::from("UnknownType body too short"))),
+    }
+}
+
+fn check_body_allowed_for(role: RoleType, body: &[u8]) -> Result<(), FcgiError> {
+    if role == RoleType::Authorizer && !body.is_empty() {
+        return Err(FcgiError::Protocol(String::from(
+            "Authorizer requests cannot carry a body",
+        )));
+    }
+
+    Ok(())
+}
+
+// `declared` is the CONTENT_LENGTH param that will count, if any.
+fn check_declared_length(
+    declared: Option<&KeyValuePair>,
+    body_len: usize,
+) -> Result<(), FcgiError> {
+    let declared = match declared {
+        Some(kv) => kv.value(),
+        None => return Ok(()),
+    };
+
+    if declared.trim().parse::<usize>().ok() != Some(body_len) {
+        return Err(FcgiError::Protocol(format!(
+            "CONTENT_LENGTH is {} but the body is {} bytes",
+            declared, body_len
+        )));
+    }
+
+    Ok(())
+}
+
+// Only Stdout, Stderr and EndRequest belong in a response.
+pub(crate) fn check_response_type(record_type: u8, body: &[u8]) -> Result<(), FcgiError> {
+    let record_type = ParsedRecordType::from_u8(record_type);
+    match record_type {
+        ParsedRecordType::Known(RecordType::Stdout)
+        | ParsedRecordType::Known(RecordType::Stderr)
+        | ParsedRecordType::Known(RecordType::EndRequest) => Ok(()),
+        _ => Err(FcgiError::UnexpectedRecord {
+            record_type,
+            length: body.len() as u16,
+        }),
+    }
+}
+
+// A whole record's version byte, type byte, request id and content.
+fn split_record(mut record: Vec<u8>) -> (u8, u8, u16, Vec<u8>) {
+    let header = HeaderView::parse(&record).expect("record has a full header");
+    let version = header.version();
+    let record_type = header.type_byte();
+    let request_id = header.request_id();
+    let content_end = 8 + usize::from(header.content_length());
+    record.truncate(content_end);
+    record.drain(..8);
+
+    (version, record_type, request_id, record)
+}
+
+// Without multiplexing every inbound record must belong to the request we
+// sent; anything else means we've lost track of the stream.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S4.2
+pub(crate) fn check_request_id(got: u16, expected: u16) -> Result<(), FcgiError> {
+    if got != expected {
+        return Err(FcgiError::RequestIdMismatch { expected, got });
+    }
+
+    Ok(())
+}
+
+// Lets the connection be registered with poll/epoll/mio for readiness
+// notifications. Only readiness should be waited on through the
+// descriptor; reading or writing it directly would desync the records.
+#[cfg(unix)]
+impl<C: Read + Write + AsRawFd> AsRawFd for Server<C> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.downstream.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl<C: Read + Write + AsFd> AsFd for Server<C> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.downstream.as_fd()
+    }
+}
+
+impl<C: Read + Write + StreamControl> Server<C> {
+    // Writes a complete request and closes the connection without reading
+    // anything back, e.g. to warm up php-fpm workers. All stream
+    // terminators are still sent so the app doesn't wait for more input.
+    pub fn send_and_close(mut self, body: &[u8]) -> Result<(), FcgiError> {
+        let out = self.encode_full_request(0, body)?;
+        self.downstream.write_all(&out)?;
+        self.downstream.flush()?;
+        self.downstream.close_write()?;
+
+        Ok(())
+    }
+
+    // Half-closes the connection once a request has been sent in full,
+    // so the app sees end of input. Some apps wait for this before
+    // answering when KEEP_CONN isn't set. The response can still be read
+    // afterwards, but no further request fits on this connection.
+    pub fn finish_request(&mut self) -> Result<(), FcgiError> {
+        self.downstream.flush()?;
+        self.downstream.close_write()?;
+
+        Ok(())
+    }
+
+    // Shuts the connection down in both directions instead of leaving it
+    // to drop. A peer that already hung up is not an error.
+    pub fn close(mut self) -> Result<(), FcgiError> {
+        self.downstream.flush()?;
+        match self.downstream.close() {
+            Err(e) if e.kind() != ErrorKind::NotConnected => Err(FcgiError::Io(e)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        self.downstream.set_nonblocking(nonblocking)
+    }
+
+    // Bounds how long a single read or write on the connection may block,
+    // so a hung app can't hold the caller forever. None waits
+    // indefinitely. A read that times out before any of the response has
+    // arrived fails with FcgiError::Io, kind WouldBlock or TimedOut
+    // depending on the platform; one that times out later fails with
+    // FcgiError::Incomplete, the same kind in its source.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.downstream.set_read_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>, Error> {
+        self.downstream.read_timeout()
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.downstream.set_write_timeout(timeout)
+    }
+
+    // Cheap liveness check for pooled connections: asks for
+    // FCGI_MPXS_CONNS, which every app can answer without running a
+    // script. True if a well-formed GetValuesResult arrives within
+    // PING_WAIT. False means the connection should be thrown away; Err
+    // only if the read timeout couldn't be set or restored. Only use it
+    // between requests.
+    pub fn ping(&mut self) -> Result<bool, FcgiError> {
+        let previous = self.downstream.read_timeout()?;
+        self.downstream.set_read_timeout(Some(PING_WAIT))?;
+        let alive = self.query_values(&["FCGI_MPXS_CONNS"]).is_ok();
+        self.downstream.set_read_timeout(previous)?;
+
+        Ok(alive)
+    }
+
+    // Not every app honors KEEP_CONN. This sends one bodiless KEEP_CONN
+    // request with the stored params, reads its response, and then checks
+    // whether the app hung up anyway. A true result means the connection
+    // is still open and can carry the next request.
+    pub fn supports_keep_alive(&mut self) -> Result<bool, FcgiError> {
+        let out = self.encode_full_request(KEEP_CONN, &[])?;
+        self.downstream.write_all(&out)?;
+        self.last_begin = Some(BeginRequest::new(self.role, KEEP_CONN, [0; 5]));
+        self.in_flight = true;
+        self.read_response()?;
+
+        let closed = self.downstream.peer_closed(KEEP_ALIVE_PROBE_WAIT)?;
+        Ok(!closed)
+    }
+}
+
+// Smallest read request made while gathering inbound records.
+const READ_AHEAD: usize = 8192;
+
+// How long ping waits for the GetValuesResult.
+const PING_WAIT: Duration = Duration::from_millis(500);
+
+// How long supports_keep_alive waits for the app to close the connection
+// after EndRequest before deciding it was kept open.
+const KEEP_ALIVE_PROBE_WAIT: Duration = Duration::from_millis(50);
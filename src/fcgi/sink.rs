@@ -0,0 +1,123 @@
+// A request body you can `write!` into. Bytes are collected into Stdin
+// records of up to u16::MAX bytes (or max_record_len) and written out as
+// each fills, on flush, and finally with the empty closing record on
+// finish (or drop).
+
+use std::io::{Error, Write};
+
+use super::{PaddingPolicy, ParamsEncoder, Record, RecordType};
+
+pub struct StdinSink<W: Write> {
+    writer: Option<W>,
+    buf: Vec<u8>,
+    request_id: u16,
+    padding: PaddingPolicy,
+    max_content: usize,
+}
+
+impl<W: Write> StdinSink<W> {
+    pub fn new(writer: W) -> StdinSink<W> {
+        StdinSink {
+            writer: Some(writer),
+            buf: Vec::new(),
+            request_id: 1,
+            padding: PaddingPolicy::None,
+            max_content: u16::MAX.into(),
+        }
+    }
+
+    // The request the records belong to; 1 by default.
+    pub fn request_id(mut self, request_id: u16) -> StdinSink<W> {
+        self.request_id = request_id;
+        self
+    }
+
+    pub fn padding(mut self, padding: PaddingPolicy) -> StdinSink<W> {
+        self.padding = padding;
+        self
+    }
+
+    // Largest content put in one record, as Server::set_max_record_len.
+    // 0 is taken as 1.
+    pub fn max_record_len(mut self, max_len: u16) -> StdinSink<W> {
+        self.max_content = max_len.max(1).into();
+        self
+    }
+
+    // Writes any buffered body and the closing empty Stdin record, and
+    // hands back the writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.close()?;
+        Ok(self.writer.take().expect("writer is only taken by finish"))
+    }
+
+    fn writer(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer is only taken by finish")
+    }
+
+    fn write_record(&mut self, content: Vec<u8>) -> Result<(), Error> {
+        let padding = self.padding.padding_length(content.len());
+        let id = self.request_id;
+        let record = Record::record_for_request(RecordType::Stdin, content, padding, id)
+            .map_err(|e| Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        self.writer().write_all(&record.to_vec_u8())
+    }
+
+    fn write_buffered(&mut self) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let content = std::mem::replace(&mut self.buf, Vec::with_capacity(self.max_content));
+        self.write_record(content)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.write_buffered()?;
+        self.write_record(Vec::new())?;
+        self.writer().flush()
+    }
+}
+
+impl<W: Write> Write for StdinSink<W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let take = (self.max_content - self.buf.len()).min(data.len());
+        self.buf.extend_from_slice(&data[..take]);
+
+        if self.buf.len() == self.max_content {
+            self.write_buffered()?;
+        }
+
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.write_buffered()?;
+        self.writer().flush()
+    }
+}
+
+// Dropping an unfinished sink still closes the Stdin stream so the app
+// doesn't wait forever; errors can't be reported from here, so call
+// finish to see them.
+impl<W: Write> Drop for StdinSink<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.close();
+        }
+    }
+}
+
+impl ParamsEncoder {
+    // Closes the Params stream, writes the request so far to `writer`, and
+    // returns a sink for streaming the body after it.
+    pub fn params_done_into_sink<W: Write>(self, mut writer: W) -> Result<StdinSink<W>, Error> {
+        let head = self
+            .params_done()
+            .map_err(|e| Error::new(std::io::ErrorKind::InvalidInput, e))?
+            .into_bytes();
+        writer.write_all(&head)?;
+
+        Ok(StdinSink::new(writer))
+    }
+}
@@ -0,0 +1,14 @@
+// Per-request measurements from Server::execute_timed. Times are in
+// nanoseconds: connect_ns covers opening the connection (0 when the
+// Server was handed a stream that was already connected), ttfb_ns runs
+// from the first byte written to the first record header read back, and
+// total_ns from the first byte written to EndRequest. Byte counts include
+// record headers and padding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestStats {
+    pub connect_ns: u64,
+    pub ttfb_ns: u64,
+    pub total_ns: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
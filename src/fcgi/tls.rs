@@ -0,0 +1,90 @@
+// FastCGI over TLS, for managed backends that only accept encrypted
+// connections. The stream is rustls's StreamOwned, which Server takes
+// like any other Read + Write:
+//
+//     let config = Arc::new(
+//         ClientConfig::builder_with_provider(provider)
+//             .with_safe_default_protocol_versions()?
+//             .with_root_certificates(roots)
+//             .with_no_client_auth(),
+//     );
+//     let mut server = server_from_tls(params, "fcgi.internal", 9443, config)?;
+//     let response = server.execute(b"")?;
+//
+// The handshake runs on the first write, so certificate problems show up
+// as an I/O error from the first request rather than from connecting.
+
+use std::io::{Error, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+
+use super::server::{connect_tcp_timeout, read_for_close, StreamControl, TcpOptions};
+use super::{FcgiError, Server};
+
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+pub type TlsServer = Server<TlsStream>;
+
+// Connects to host:port and sets up a TLS session for `host`, which is
+// also the name the certificate is checked against.
+pub fn server_from_tls(
+    params_raw: Vec<(String, String)>,
+    host: &str,
+    port: u16,
+    config: Arc<ClientConfig>,
+) -> Result<TlsServer, FcgiError> {
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|e| FcgiError::Protocol(format!("bad TLS server name {}: {}", host, e)))?;
+    let conn = ClientConnection::new(config, name).map_err(|e| FcgiError::Io(Error::other(e)))?;
+    let sock = connect_tcp_timeout((host, port), None)?;
+
+    Ok(Server::from_stream(params_raw, StreamOwned::new(conn, sock)))
+}
+
+impl StreamControl for TlsStream {
+    // Sends close_notify before half-closing the socket, so the app sees
+    // a clean end of the TLS stream rather than a truncation.
+    fn close_write(&mut self) -> Result<(), Error> {
+        self.conn.send_close_notify();
+        self.flush()?;
+        self.sock.shutdown(std::net::Shutdown::Write)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.conn.send_close_notify();
+        self.flush()?;
+        self.sock.shutdown(std::net::Shutdown::Both)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        self.sock.set_nonblocking(nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn read_timeout(&self) -> Result<Option<Duration>, Error> {
+        self.sock.read_timeout()
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.sock.set_write_timeout(timeout)
+    }
+
+    fn set_tcp_options(&self, options: &TcpOptions) -> Result<(), Error> {
+        self.sock.set_tcp_options(options)
+    }
+
+    fn peer_closed(&mut self, wait: Duration) -> Result<bool, Error> {
+        read_for_close(
+            self,
+            |s: &TlsStream| s.sock.read_timeout(),
+            |s: &TlsStream, timeout| s.sock.set_read_timeout(timeout),
+            wait,
+        )
+    }
+}
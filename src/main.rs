@@ -1,62 +1,118 @@
-mod fcgi;
-use std::os::unix::net::UnixStream;
-
-fn main() -> std::io::Result<()> {
-    // NEXT STEPS:
-    // 1) get a minimal php-fpm dockerfile running
-    // 2) attempt to manually send a minimal FastCGI request to that server
-    // 3) spit out the response in stdout
-    //
-    // THEN: refactor so that a request struct is more abstracted; enum for body types BeginRequest
-    // | EndRequest | KeyValue | Other
-    // THEN: work on turning incoming HTTP requests into FastCGI requests
-
-    let kvs_for_init: Vec<(String, String)> = vec![
-        ("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string()),
-        ("SERVER_ADDR".to_string(), "127.0.0.1".to_string()),
-        ("SERVER_PROTOCOL".to_string(), "HTTP/2.0".to_string()),
-        ("SERVER_SOFTWARE".to_string(), "Crustaceous/trunk".to_string()),
-        ("REQUEST_METHOD".to_string(), "GET".to_string()),
-        ("REMOTE_ADDR".to_string(), "127.0.0.1".to_string()),
-        ("SCRIPT_FILENAME".to_string(), "/var/www/html/index.php".to_string()),
-    ];
-
-    let mut server: fcgi::Server<UnixStream> = match fcgi::server_from_unix_path(
-        kvs_for_init,
-        "/var/run/php/php8.2-fpm.sock".to_string()
-    ) {
-        fcgi::ConcreteServer::UnixServer(s) => s,
-        other => panic!("Got an unexpected server type")
-    };
-
-    let begin_body = fcgi::BeginRequest::new(fcgi::RoleType::Responder, 0, [0; 5]);
-    let begin_bytes = begin_body.to_vec_u8().expect("Serialization failed");
-    println!("begin bytes: {:?}", begin_bytes);
-    let begin_rec = fcgi::Record::record_from_data(fcgi::RecordType::BeginRequest, begin_bytes, 0)
-        .expect("Record creation failed");
-
-    // Result type definitely allows a better way to do this
-    let kv_records: Vec<u8> = server.serialize_params();
-
-    let stdin_record = fcgi::Record::record_from_data(fcgi::RecordType::Stdin, vec![], 0)
-        .expect("record creation failed");
-
-    let mut out: Vec<u8> = Vec::new();
-
-    out.extend(begin_rec.to_vec_u8());
-
-    out.extend(kv_records);
+use rsfcgi::fcgi;
+use std::io::Write;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: rsfcgi [--socket PATH] [--method METHOD] [--uri URI] [--script PATH] \
+[--protocol SERVER_PROTOCOL] [--host HOST] [--param KEY=VAL]... [--body-file PATH]";
+
+struct Options {
+    socket: String,
+    method: String,
+    uri: String,
+    script: String,
+    protocol: String,
+    host: String,
+    params: Vec<(String, String)>,
+    body_file: Option<String>,
+}
 
-    out.extend(stdin_record.to_vec_u8());
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        socket: "/var/run/php/php8.2-fpm.sock".to_string(),
+        method: "GET".to_string(),
+        uri: "/index.php".to_string(),
+        script: "/var/www/html/index.php".to_string(),
+        protocol: "HTTP/1.1".to_string(),
+        host: "localhost".to_string(),
+        params: Vec::new(),
+        body_file: None,
+    };
 
-    server.send_request(out)?;
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--socket" => options.socket = value()?,
+            "--method" => options.method = value()?,
+            "--uri" => options.uri = value()?,
+            "--script" => options.script = value()?,
+            "--protocol" => options.protocol = value()?,
+            "--host" => options.host = value()?,
+            "--body-file" => options.body_file = Some(value()?),
+            "--param" => {
+                let pair = value()?;
+                let (k, v) = pair
+                    .split_once('=')
+                    .ok_or(format!("--param expects KEY=VAL, got {}", pair))?;
+                options.params.push((k.to_string(), v.to_string()));
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+
+    Ok(options)
+}
 
-    let mut res_s = String::new();
+fn run(options: Options) -> Result<fcgi::FcgiResponse, fcgi::FcgiError> {
+    let body = match &options.body_file {
+        Some(path) => std::fs::read(path)?,
+        None => Vec::new(),
+    };
 
+    let mut params = fcgi::Params::cgi_defaults("127.0.0.1", "Crustaceous/trunk");
+    params.set("SERVER_PROTOCOL", &options.protocol);
+    params.set_host(&options.host);
+    let request = fcgi::params_from_http(
+        &options.method,
+        &options.uri,
+        &[],
+        "127.0.0.1",
+        &options.script,
+    );
+    for (k, v) in &request {
+        params.set(k, v);
+    }
+    if !body.is_empty() {
+        params.set("CONTENT_LENGTH", &body.len().to_string());
+    }
+    for (k, v) in &options.params {
+        params.set(k, v);
+    }
+
+    fcgi::fcgi_request(&options.socket, params.into_vec(), &body)
+}
 
-    server.consume_response_to_string(&mut res_s)?;
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    println!("{}", res_s);
+    let response = match run(options) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    Ok(())
+    if response.app_status != 0 {
+        eprintln!("warning: app exited with status {}", response.app_status);
+    }
+    for line in response.stderr_lines() {
+        eprintln!("stderr: {}", line);
+    }
+    if response.is_primary_script_unknown() {
+        eprintln!("hint: the app couldn't find the script; check --script");
+    }
+    println!("Status: {}", response.http_status);
+    for (k, v) in &response {
+        println!("{}: {}", k, v);
+    }
+    println!();
+    let _ = std::io::stdout().write_all(response.body());
+
+    ExitCode::SUCCESS
 }
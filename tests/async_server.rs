@@ -0,0 +1,92 @@
+#![cfg(feature = "tokio")]
+
+use rsfcgi::fcgi::{
+    AsyncServer, EndRequest, FcgiError, FcgiResponse, ProtocolStatus, Record, RecordType,
+};
+use tokio::io::{duplex, AsyncWriteExt};
+
+fn record(record_type: RecordType, content: &[u8]) -> Vec<u8> {
+    Record::record_from_data(record_type, content.to_vec(), 0).expect("fits").to_vec_u8()
+}
+
+fn end() -> Vec<u8> {
+    let body = EndRequest::new(0, ProtocolStatus::RequestComplete).to_vec_u8();
+    record(RecordType::EndRequest, &body)
+}
+
+// Reads the response to `reply` as it would arrive from the app.
+fn respond<F>(reply: Vec<u8>, setup: F) -> Result<FcgiResponse, FcgiError>
+where
+    F: FnOnce(&mut AsyncServer<tokio::io::DuplexStream>),
+{
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(async {
+        let (client, mut app) = duplex(reply.len() + 1);
+        app.write_all(&reply).await?;
+        drop(app);
+        let mut server = AsyncServer::new(Vec::new(), client);
+        setup(&mut server);
+
+        server.consume_response().await
+    })
+}
+
+#[test]
+fn reads_a_response() -> Result<(), FcgiError> {
+    let mut reply = record(RecordType::Stderr, b"notice");
+    reply.extend(record(RecordType::Stdout, b"Content-Type: text/plain\r\n\r\nhi"));
+    reply.extend(record(RecordType::Stdout, b""));
+    reply.extend(end());
+    let response = respond(reply, |_| {})?;
+
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(response.body(), b"hi");
+    assert_eq!(response.stderr, b"notice");
+    Ok(())
+}
+
+#[test]
+fn response_limits() {
+    let mut reply = record(RecordType::Stdout, b"abc");
+    reply.extend(record(RecordType::Stdout, b"de"));
+    reply.extend(end());
+
+    let bytes = respond(reply.clone(), |server| server.set_response_limits(4, usize::MAX));
+    assert!(matches!(
+        bytes,
+        Err(FcgiError::ResponseTooLarge {
+            bytes: 5,
+            records: 2
+        })
+    ));
+    let records = respond(reply, |server| server.set_response_limits(usize::MAX, 2));
+    assert!(matches!(
+        records,
+        Err(FcgiError::ResponseTooLarge {
+            bytes: 13,
+            records: 3
+        })
+    ));
+}
+
+// A record left over from another request fails the response unless
+// request ids aren't strict, when it is dropped.
+#[test]
+fn stale_records() -> Result<(), FcgiError> {
+    let mut reply = Record::record_for_request(RecordType::Stdout, b"old".to_vec(), 0, 2)?
+        .to_vec_u8();
+    reply.extend(record(RecordType::Stdout, b"\r\n\r\nnew"));
+    reply.extend(end());
+
+    let strict = respond(reply.clone(), |_| {});
+    assert!(matches!(
+        strict,
+        Err(FcgiError::RequestIdMismatch {
+            expected: 1,
+            got: 2
+        })
+    ));
+    let lenient = respond(reply, |server| server.set_strict_request_ids(false))?;
+    assert_eq!(lenient.body(), b"new");
+    Ok(())
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "std")]
+
+use std::io::{ErrorKind, Read};
+
+use rsfcgi::fcgi::ChunkedBodyReader;
+
+#[test]
+fn decodes_chunks_and_drops_trailers() {
+    let body = b"4;name=value\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\n";
+    let mut reader = ChunkedBodyReader::new(&body[..]);
+
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).unwrap();
+    assert_eq!(out, b"Wikipedia");
+    assert_eq!(reader.decoded_len(), 9);
+}
+
+// A size line with no end in sight is refused rather than buffered.
+#[test]
+fn long_size_line() {
+    let mut body = b"4;".to_vec();
+    body.extend(vec![b'x'; 100_000]);
+    let mut reader = ChunkedBodyReader::new(&body[..]);
+
+    let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn long_trailer_line() {
+    let mut body = b"0\r\nX-Trailer: ".to_vec();
+    body.extend(vec![b'x'; 10_000]);
+    body.extend(b"\r\n\r\n");
+    let mut reader = ChunkedBodyReader::new(&body[..]);
+
+    let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+// Extensions and trailers short of the cap are fine.
+#[test]
+fn lines_under_the_cap() {
+    let mut body = format!("3;ext={}\r\nabc\r\n0\r\n", "e".repeat(4000)).into_bytes();
+    body.extend(b"\r\n");
+    let mut reader = ChunkedBodyReader::new(&body[..]);
+
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).unwrap();
+    assert_eq!(out, b"abc");
+}
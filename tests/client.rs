@@ -0,0 +1,101 @@
+// FastCgiClient against a backend on a local TCP socket that reads the
+// request up to the empty Stdin record and answers with canned records.
+#![cfg(feature = "std")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use rsfcgi::fcgi::{
+    EndRequest, FastCgiClient, FcgiError, HeaderView, KeyValuePair, ProtocolStatus, Record,
+    RecordType,
+};
+
+fn record(record_type: RecordType, content: &[u8]) -> Vec<u8> {
+    Record::record_from_data(record_type, content.to_vec(), 0)
+        .expect("content fits")
+        .to_vec_u8()
+}
+
+// The Params and Stdin streams the backend was sent.
+struct Received {
+    params: Vec<u8>,
+    stdin: Vec<u8>,
+}
+
+fn read_request(conn: &mut TcpStream) -> Received {
+    let mut received = Received {
+        params: Vec::new(),
+        stdin: Vec::new(),
+    };
+    loop {
+        let mut header = [0; 8];
+        conn.read_exact(&mut header).expect("record header");
+        let view = HeaderView::new(&header);
+        let mut body = vec![0; view.body_length()];
+        conn.read_exact(&mut body).expect("record body");
+        body.truncate(view.content_length().into());
+
+        if view.type_byte() == RecordType::Params as u8 {
+            received.params.extend(body);
+        } else if view.type_byte() == RecordType::Stdin as u8 {
+            if body.is_empty() {
+                return received;
+            }
+            received.stdin.extend(body);
+        }
+    }
+}
+
+fn backend(reply: Vec<u8>) -> Result<(String, JoinHandle<Received>), FcgiError> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?.to_string();
+    let handle = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().expect("accept");
+        let received = read_request(&mut conn);
+        conn.write_all(&reply).expect("write reply");
+        received
+    });
+
+    Ok((addr, handle))
+}
+
+#[test]
+fn send_returns_the_parsed_response() -> Result<(), FcgiError> {
+    let mut reply = record(RecordType::Stdout, b"Status: 201 Created\r\n");
+    reply.extend(record(RecordType::Stdout, b"Content-Type: text/plain\r\n\r\nmade it"));
+    reply.extend(record(RecordType::Stdout, b""));
+    let end = EndRequest::new(0, ProtocolStatus::RequestComplete).to_vec_u8();
+    reply.extend(record(RecordType::EndRequest, &end));
+    let (addr, handle) = backend(reply)?;
+
+    let params = vec![
+        (String::from("REQUEST_METHOD"), String::from("POST")),
+        (String::from("CONTENT_LENGTH"), String::from("5")),
+    ];
+    let response = FastCgiClient::connect(addr.as_str())?.send(params, b"hello")?;
+    assert_eq!(response.http_status, 201);
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(response.body(), b"made it");
+
+    let received = handle.join().expect("backend thread");
+    let params = KeyValuePair::parse_all(&received.params)?;
+    let params: Vec<(&str, &str)> = params.iter().map(|kv| (kv.name(), kv.value())).collect();
+    assert_eq!(params, [("REQUEST_METHOD", "POST"), ("CONTENT_LENGTH", "5")]);
+    assert_eq!(received.stdin, b"hello");
+    Ok(())
+}
+
+#[test]
+fn connect_refused() -> Result<(), FcgiError> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?.to_string();
+    drop(listener);
+
+    match FastCgiClient::connect(addr.as_str()) {
+        Err(FcgiError::ConnectionRefused(refused)) => assert_eq!(refused, addr),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("connected to a closed port"),
+    }
+    Ok(())
+}
@@ -0,0 +1,6 @@
+use rsfcgi::fcgi::{self, FcgiError};
+
+#[test]
+fn body_types_round_trip() -> Result<(), FcgiError> {
+    fcgi::verify()
+}
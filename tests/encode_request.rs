@@ -0,0 +1,81 @@
+use rsfcgi::fcgi::{
+    encode_request_into, BeginRequest, FcgiError, KeyValuePair, Record, RecordType, RoleType,
+};
+
+fn records(mut bytes: &[u8]) -> Result<Vec<Record>, FcgiError> {
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let (record, used) = Record::from_bytes(bytes)?;
+        records.push(record);
+        bytes = &bytes[used..];
+    }
+
+    Ok(records)
+}
+
+fn stream(records: &[Record], record_type: RecordType) -> Vec<&Record> {
+    records.iter().filter(|r| r.record_type() == record_type).collect()
+}
+
+// Small pairs share one record instead of getting one each.
+#[test]
+fn params_share_a_record() -> Result<(), FcgiError> {
+    let params = [
+        KeyValuePair::new("REQUEST_METHOD".into(), "GET".into()),
+        KeyValuePair::new("SCRIPT_NAME".into(), "/index.php".into()),
+    ];
+    let begin = BeginRequest::new(RoleType::Responder, 0, [0; 5]);
+    let mut buf = Vec::new();
+    encode_request_into(&mut buf, 1, &begin, &params, b"body")?;
+
+    let records = records(&buf)?;
+    let params_records = stream(&records, RecordType::Params);
+    let mut expected = params[0].to_vec_u8()?;
+    expected.extend(params[1].to_vec_u8()?);
+    assert_eq!(params_records.len(), 2);
+    assert_eq!(params_records[0].content(), expected);
+    assert!(params_records[1].content().is_empty());
+    Ok(())
+}
+
+// A pair too long for one record is split across several, and every
+// record carries the request id asked for.
+#[test]
+fn long_pair_split_across_records() -> Result<(), FcgiError> {
+    let cookie = KeyValuePair::new("HTTP_COOKIE".into(), "c".repeat(70_000));
+    let begin = BeginRequest::new(RoleType::Responder, 0, [0; 5]);
+    let mut buf = Vec::new();
+    encode_request_into(&mut buf, 7, &begin, std::slice::from_ref(&cookie), b"")?;
+
+    let records = records(&buf)?;
+    assert!(records.iter().all(|r| r.request_id() == 7));
+    let params_records = stream(&records, RecordType::Params);
+    assert_eq!(params_records.len(), 3);
+    assert_eq!(params_records[0].content().len(), usize::from(u16::MAX));
+    let joined: Vec<u8> = params_records.iter().flat_map(|r| r.content().to_vec()).collect();
+    assert_eq!(joined, cookie.to_vec_u8()?);
+    Ok(())
+}
+
+#[test]
+fn no_params_gives_only_the_closing_record() -> Result<(), FcgiError> {
+    let begin = BeginRequest::new(RoleType::Responder, 0, [0; 5]);
+    let mut buf = Vec::new();
+    encode_request_into(&mut buf, 1, &begin, &[], b"")?;
+
+    let records = records(&buf)?;
+    let params_records = stream(&records, RecordType::Params);
+    assert_eq!(params_records.len(), 1);
+    assert!(params_records[0].content().is_empty());
+    Ok(())
+}
+
+// The role is a big-endian u16, then the flags and five reserved bytes.
+#[test]
+fn begin_request_body() -> Result<(), FcgiError> {
+    let begin = BeginRequest::new(RoleType::Filter, 1, [0; 5]);
+    let bytes = begin.to_vec_u8()?;
+    assert_eq!(bytes, [0, 3, 1, 0, 0, 0, 0, 0]);
+    assert_eq!(BeginRequest::from_bytes(&bytes)?, begin);
+    Ok(())
+}
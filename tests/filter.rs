@@ -0,0 +1,98 @@
+#![cfg(feature = "std")]
+
+use rsfcgi::fcgi::{
+    filter_data_params, BeginRequest, FcgiError, MockBackend, Params, Record, RecordType, RoleType,
+    Server,
+};
+
+fn contents(records: &[Record], record_type: RecordType) -> Vec<u8> {
+    records
+        .iter()
+        .filter(|r| r.record_type() == record_type)
+        .flat_map(|r| r.content().to_vec())
+        .collect()
+}
+
+// Stdin is sent and closed before the Data stream starts, and the app's
+// filtered file comes back as the body.
+#[test]
+fn stdin_then_data() -> Result<(), FcgiError> {
+    let data = b"file to filter";
+    let backend = MockBackend::new()
+        .stdout(b"Content-Type: text/plain\r\n\r\n")
+        .stdout(data)
+        .stdout(b"")
+        .end(0);
+    let params = filter_data_params(0, data.len());
+    let mut server = Server::with_role(params, backend, RoleType::Filter);
+    let response = server.execute_filter(b"client body", data)?;
+    assert_eq!(response.body(), data);
+
+    // One Params record per pair, so only the closing one is listed.
+    let sent = server.get_ref().sent_records()?;
+    let order: Vec<(RecordType, bool)> = sent
+        .iter()
+        .map(|r| (r.record_type(), r.content().is_empty()))
+        .filter(|&(record_type, empty)| record_type != RecordType::Params || empty)
+        .collect();
+    assert_eq!(
+        order,
+        [
+            (RecordType::BeginRequest, false),
+            (RecordType::Params, true),
+            (RecordType::Stdin, false),
+            (RecordType::Stdin, true),
+            (RecordType::Data, false),
+            (RecordType::Data, true),
+        ]
+    );
+    assert_eq!(BeginRequest::from_bytes(sent[0].content())?.role(), RoleType::Filter);
+    assert_eq!(contents(&sent, RecordType::Stdin), b"client body");
+    assert_eq!(contents(&sent, RecordType::Data), data);
+    Ok(())
+}
+
+// A Data stream longer than one record is split, still after Stdin.
+#[test]
+fn long_data_split_across_records() -> Result<(), FcgiError> {
+    let data = vec![b'd'; 70_000];
+    let backend = MockBackend::new().stdout(&data).stdout(b"").end(0);
+    let params = filter_data_params(0, data.len());
+    let mut server = Server::with_role(params, backend, RoleType::Filter);
+    let response = server.execute_filter(b"", &data)?;
+    assert_eq!(response.stdout, data);
+
+    let sent = server.get_ref().sent_records()?;
+    let first_data = sent.iter().position(|r| r.record_type() == RecordType::Data);
+    let last_stdin = sent.iter().rposition(|r| r.record_type() == RecordType::Stdin);
+    assert!(last_stdin < first_data);
+    assert_eq!(sent.iter().filter(|r| r.record_type() == RecordType::Data).count(), 3);
+    assert_eq!(contents(&sent, RecordType::Data), data);
+    Ok(())
+}
+
+// request_with_params sends no data, but a Filter still needs the Data
+// stream closed before it answers.
+#[test]
+fn request_with_params_closes_data() -> Result<(), FcgiError> {
+    let backend = MockBackend::new().stdout(b"\r\n\r\nok").stdout(b"").end(0);
+    let mut server = Server::with_role(filter_data_params(0, 0), backend, RoleType::Filter);
+    let mut extra = Params::new();
+    extra.set("REQUEST_URI", "/filtered");
+    let response = server.request_with_params(&extra, b"body")?;
+    assert_eq!(response.body(), b"ok");
+
+    let sent = server.get_ref().sent_records()?;
+    let last = sent.last().map(|r| (r.record_type(), r.content().is_empty()));
+    assert_eq!(last, Some((RecordType::Data, true)));
+    assert_eq!(contents(&sent, RecordType::Stdin), b"body");
+    Ok(())
+}
+
+#[test]
+fn only_filters_take_data() {
+    let mut server = Server::from_stream(Vec::new(), MockBackend::new());
+
+    assert!(matches!(server.execute_filter(b"", b"data"), Err(FcgiError::Protocol(_))));
+    assert!(server.get_ref().sent().is_empty());
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "std")]
+
+use rsfcgi::fcgi::{
+    FcgiError, GetValues, KeyValuePair, MockBackend, Record, RecordType, Server,
+    FCGI_NULL_REQUEST_ID,
+};
+
+fn result_record(content: &[u8]) -> Result<Record, FcgiError> {
+    Record::record_for_request(
+        RecordType::GetValuesResult,
+        content.to_vec(),
+        0,
+        FCGI_NULL_REQUEST_ID,
+    )
+}
+
+// The GetValuesResult stream cut in the middle of a pair, then closed by
+// an empty record; the pairs come back whole and the empty record doesn't
+// spill into the next response.
+#[test]
+fn result_split_across_records() -> Result<(), FcgiError> {
+    let mut stream = KeyValuePair::new("FCGI_MAX_CONNS".into(), "10".into()).to_vec_u8()?;
+    stream.extend(KeyValuePair::new("FCGI_MPXS_CONNS".into(), "1".into()).to_vec_u8()?);
+    let backend = MockBackend::new()
+        .record(result_record(&stream[..5])?)
+        .record(result_record(&stream[5..])?)
+        .record(result_record(b"")?)
+        .stdout(b"\r\n\r\nok")
+        .end(0);
+    let mut server = Server::from_stream(Vec::new(), backend);
+
+    let values = server.query_values(&["FCGI_MAX_CONNS", "FCGI_MPXS_CONNS"])?;
+    assert_eq!(
+        values,
+        [
+            (String::from("FCGI_MAX_CONNS"), String::from("10")),
+            (String::from("FCGI_MPXS_CONNS"), String::from("1")),
+        ]
+    );
+    assert_eq!(server.consume_response()?.body(), b"ok");
+
+    let sent = server.get_ref().sent_records()?;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].record_type(), RecordType::GetValues);
+    assert_eq!(sent[0].request_id(), FCGI_NULL_REQUEST_ID);
+    let names = GetValues::from_bytes(sent[0].content())?;
+    assert_eq!(names.names(), ["FCGI_MAX_CONNS", "FCGI_MPXS_CONNS"]);
+    Ok(())
+}
+
+#[test]
+fn unknown_names_are_absent() -> Result<(), FcgiError> {
+    let stream = KeyValuePair::new("FCGI_MAX_REQS".into(), "50".into()).to_vec_u8()?;
+    let backend = MockBackend::new().record(result_record(&stream)?);
+    let mut server = Server::from_stream(Vec::new(), backend);
+
+    let values = server.get_values(&["FCGI_MAX_REQS", "X_NOT_A_VARIABLE"])?;
+    assert_eq!(values.len(), 1);
+    assert_eq!(values["FCGI_MAX_REQS"], "50");
+    Ok(())
+}
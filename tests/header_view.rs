@@ -0,0 +1,43 @@
+use rsfcgi::fcgi::{FcgiError, HeaderView, ParsedRecordType, Record, RecordType};
+
+// Content length is big-endian in bytes 4 and 5; the padding length in
+// byte 6 isn't part of it.
+#[test]
+fn content_length_is_big_endian() {
+    let header = [1, RecordType::Stdout as u8, 0x12, 0x34, 0x01, 0x02, 6, 0];
+    let view = HeaderView::new(&header);
+
+    assert_eq!(view.content_length(), 0x0102);
+    assert_eq!(Record::content_length_from_header_bytes(&header), 0x0102);
+    assert_eq!(view.padding_length(), 6);
+    assert_eq!(view.body_length(), 0x0102 + 6);
+    assert_eq!(view.request_id(), 0x1234);
+    assert_eq!(view.record_type(), ParsedRecordType::Known(RecordType::Stdout));
+}
+
+#[test]
+fn largest_content_length() {
+    let header = [1, RecordType::Stdin as u8, 0, 1, 0xff, 0xff, 0xff, 0];
+
+    assert_eq!(Record::content_length_from_header_bytes(&header), u16::MAX);
+    assert_eq!(HeaderView::new(&header).body_length(), 65535 + 255);
+}
+
+#[test]
+fn matches_encoded_records() -> Result<(), FcgiError> {
+    for len in [0, 1, 255, 256, 65535] {
+        let record = Record::record_aligned(RecordType::Stdout, vec![b'x'; len])?;
+        let header = record.header_bytes();
+
+        assert_eq!(usize::from(Record::content_length_from_header_bytes(&header)), len);
+        assert_eq!(HeaderView::new(&header).body_length(), record.to_vec_u8().len() - 8);
+    }
+    Ok(())
+}
+
+#[test]
+fn parse_needs_a_whole_header() {
+    assert!(HeaderView::parse(&[1, 6, 0, 1, 0, 5, 0]).is_none());
+    let view = HeaderView::parse(&[1, 6, 0, 1, 0, 5, 0, 0, b'h']).expect("header");
+    assert_eq!(view.content_length(), 5);
+}
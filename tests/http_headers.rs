@@ -0,0 +1,49 @@
+#![cfg(feature = "http")]
+
+use http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, COOKIE, HOST};
+use rsfcgi::fcgi::ParamsBuilder;
+
+fn get<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+}
+
+// A header sent more than once becomes one variable, its values joined by
+// ", ", or by "; " for Cookie.
+#[test]
+fn repeated_headers_are_folded() {
+    let mut map = HeaderMap::new();
+    map.append(ACCEPT, HeaderValue::from_static("text/html"));
+    map.append(ACCEPT, HeaderValue::from_static("*/*"));
+    map.append(COOKIE, HeaderValue::from_static("a=1"));
+    map.append(COOKIE, HeaderValue::from_static("b=2"));
+    let params = ParamsBuilder::new().headers(&map).build();
+
+    assert_eq!(get(&params, "HTTP_ACCEPT"), Some("text/html, */*"));
+    assert_eq!(get(&params, "HTTP_COOKIE"), Some("a=1; b=2"));
+    assert_eq!(params.len(), 2);
+}
+
+#[test]
+fn headers_take_their_cgi_names() {
+    let mut map = HeaderMap::new();
+    map.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    map.insert(HOST, HeaderValue::from_static("example.com:8443"));
+    map.insert("x-request-id", HeaderValue::from_static("abc"));
+    let params = ParamsBuilder::new().headers(&map).build();
+
+    assert_eq!(get(&params, "CONTENT_TYPE"), Some("application/json"));
+    assert_eq!(get(&params, "HTTP_HOST"), Some("example.com:8443"));
+    assert_eq!(get(&params, "SERVER_NAME"), Some("example.com"));
+    assert_eq!(get(&params, "SERVER_PORT"), Some("8443"));
+    assert_eq!(get(&params, "HTTP_X_REQUEST_ID"), Some("abc"));
+}
+
+// Values that aren't UTF-8 are converted lossily rather than dropped.
+#[test]
+fn non_utf8_values() {
+    let mut map = HeaderMap::new();
+    map.insert("x-name", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+    let params = ParamsBuilder::new().headers(&map).build();
+
+    assert_eq!(get(&params, "HTTP_X_NAME"), Some("caf\u{fffd}"));
+}
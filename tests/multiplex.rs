@@ -0,0 +1,59 @@
+#![cfg(feature = "std")]
+
+use rsfcgi::fcgi::{
+    EndRequest, FcgiError, MockBackend, Multiplexer, ProtocolStatus, Record, RecordType, Server,
+};
+
+fn record(record_type: RecordType, content: &[u8], request_id: u16) -> Record {
+    Record::record_for_request(record_type, content.to_vec(), 0, request_id).expect("fits")
+}
+
+fn end(request_id: u16) -> Record {
+    let body = EndRequest::new(0, ProtocolStatus::RequestComplete).to_vec_u8();
+    record(RecordType::EndRequest, &body, request_id)
+}
+
+#[test]
+fn responses_are_kept_apart() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .record(record(RecordType::Stdout, b"\r\n\r\none", 1))
+        .record(record(RecordType::Stdout, b"\r\n\r\ntwo", 2))
+        .record(end(2))
+        .record(end(1));
+    let mut mux = Multiplexer::new(Server::from_stream(Vec::new(), backend));
+    let first = mux.begin(Vec::new(), b"")?;
+    let second = mux.begin(Vec::new(), b"")?;
+
+    assert_eq!(mux.wait(second)?.body(), b"two");
+    assert_eq!(mux.wait(first)?.body(), b"one");
+    assert_eq!(mux.in_flight(), 0);
+    Ok(())
+}
+
+// The limits apply to each response on its own. The one going past them
+// fails; the other still completes, and the rest of the failed one is
+// dropped without upsetting it.
+#[test]
+fn response_limits_per_request() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .record(record(RecordType::Stdout, b"0123456789abcdef", 1))
+        .record(record(RecordType::Stdout, b"\r\n\r\nok", 2))
+        .record(record(RecordType::Stdout, b"more", 1))
+        .record(end(2))
+        .record(end(1));
+    let mut mux = Multiplexer::new(Server::from_stream(Vec::new(), backend));
+    mux.set_response_limits(14, usize::MAX);
+    let first = mux.begin(Vec::new(), b"")?;
+    let second = mux.begin(Vec::new(), b"")?;
+
+    assert!(matches!(
+        mux.wait(first),
+        Err(FcgiError::ResponseTooLarge {
+            bytes: 16,
+            records: 1
+        })
+    ));
+    assert_eq!(mux.wait(second)?.body(), b"ok");
+    assert_eq!(mux.in_flight(), 0);
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use rsfcgi::fcgi::{FcgiError, KeyValuePair};
+
+// A 4-byte length: big-endian with the high bit of the first byte set.
+fn long_length(len: u32) -> [u8; 4] {
+    (len | 0x8000_0000).to_be_bytes()
+}
+
+// Pairs whose name and value lengths use the 1- and 4-byte forms in
+// every mix, back to back, so each pair is framed from where the last
+// one ended. The 4-byte form may also carry a length under 128.
+#[test]
+fn mixed_length_forms() -> Result<(), FcgiError> {
+    let long_value = "v".repeat(300);
+    let mut buf = Vec::new();
+    // (1, 4)
+    buf.push(4);
+    buf.extend(long_length(300));
+    buf.extend(b"HOST");
+    buf.extend(long_value.as_bytes());
+    // (4, 1)
+    buf.extend(long_length(6));
+    buf.push(2);
+    buf.extend(b"METHOD");
+    buf.extend(b"GE");
+    // (4, 4)
+    buf.extend(long_length(3));
+    buf.extend(long_length(1));
+    buf.extend(b"KEY");
+    buf.extend(b"x");
+
+    let pairs = KeyValuePair::parse_all(&buf)?;
+    let pairs: Vec<(&str, &str)> = pairs.iter().map(|kv| (kv.name(), kv.value())).collect();
+    assert_eq!(pairs, [("HOST", long_value.as_str()), ("METHOD", "GE"), ("KEY", "x")]);
+    Ok(())
+}
+
+// Every mix of short and long names and values, as the encoder writes
+// them into one buffer, comes back in order.
+#[test]
+fn short_and_long_pairs_back_to_back() -> Result<(), FcgiError> {
+    let sizes = [(5, 5), (5, 300), (300, 5), (300, 300)];
+    let originals: Vec<KeyValuePair> = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, value))| KeyValuePair::new(i.to_string().repeat(name), "v".repeat(value)))
+        .collect();
+    let mut buf = Vec::new();
+    for kv in &originals {
+        kv.write_into(&mut buf)?;
+    }
+
+    assert_eq!(KeyValuePair::parse_all(&buf)?, originals);
+    Ok(())
+}
+
+// The encoder picks the 4-byte form only past 127 bytes.
+#[test]
+fn encoded_length_forms() -> Result<(), FcgiError> {
+    let name = "N".repeat(128);
+    let bytes = KeyValuePair::new(name.clone(), "short".into()).to_vec_u8()?;
+    assert_eq!(bytes[..5], [0x80, 0, 0, 128, 5]);
+
+    let pairs = KeyValuePair::parse_all(&bytes)?;
+    assert_eq!(pairs.len(), 1);
+    assert_eq!(pairs[0].name(), name);
+    assert_eq!(pairs[0].value(), "short");
+    Ok(())
+}
+
+#[test]
+fn truncated_long_length() {
+    assert!(matches!(KeyValuePair::parse_all(&[0x80, 0, 1]), Err(FcgiError::Protocol(_))));
+}
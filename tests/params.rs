@@ -0,0 +1,65 @@
+use rsfcgi::fcgi::{cgi_header_name, params_from_http, ParamsBuilder};
+
+fn pairs(params: &[(&str, &str)]) -> Vec<(String, String)> {
+    params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+// Content-Type and Content-Length have variables of their own, in any
+// case; everything else is HTTP_ prefixed.
+#[test]
+fn content_headers_keep_their_names() {
+    assert_eq!(cgi_header_name("Content-Type"), "CONTENT_TYPE");
+    assert_eq!(cgi_header_name("content-length"), "CONTENT_LENGTH");
+    assert_eq!(cgi_header_name("CONTENT-TYPE"), "CONTENT_TYPE");
+    assert_eq!(cgi_header_name("Content-Encoding"), "HTTP_CONTENT_ENCODING");
+}
+
+#[test]
+fn dashes_become_underscores() {
+    assert_eq!(cgi_header_name("X-Forwarded-For"), "HTTP_X_FORWARDED_FOR");
+    assert_eq!(cgi_header_name("accept-language"), "HTTP_ACCEPT_LANGUAGE");
+    assert_eq!(cgi_header_name(" User-Agent "), "HTTP_USER_AGENT");
+}
+
+#[test]
+fn http_header_uses_the_cgi_name() {
+    let params = ParamsBuilder::new()
+        .http_header("content-type", "text/plain")
+        .http_header("X-Real-IP", "192.0.2.1")
+        .build();
+
+    assert_eq!(params, pairs(&[("CONTENT_TYPE", "text/plain"), ("HTTP_X_REAL_IP", "192.0.2.1")]));
+}
+
+// Host also gives SERVER_NAME, and SERVER_PORT when it names one.
+#[test]
+fn http_header_host() {
+    let params = ParamsBuilder::new().http_header("HOST", "example.com:8080").build();
+
+    assert_eq!(
+        params,
+        pairs(&[
+            ("HTTP_HOST", "example.com:8080"),
+            ("SERVER_NAME", "example.com"),
+            ("SERVER_PORT", "8080"),
+        ])
+    );
+}
+
+#[test]
+fn params_from_http_folds_repeated_headers() {
+    let headers = [
+        ("Accept", "text/html"),
+        ("Cookie", "a=1"),
+        ("accept", "*/*"),
+        ("cookie", "b=2"),
+    ];
+    let params = params_from_http("GET", "/index.php?x=1", &headers, "127.0.0.1", "/srv/index.php");
+    let get = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+
+    assert_eq!(get("HTTP_ACCEPT"), Some("text/html, */*"));
+    assert_eq!(get("HTTP_COOKIE"), Some("a=1; b=2"));
+    assert_eq!(get("QUERY_STRING"), Some("x=1"));
+    assert_eq!(get("SCRIPT_NAME"), Some("/index.php"));
+    assert_eq!(params.iter().filter(|(k, _)| k == "HTTP_ACCEPT").count(), 1);
+}
@@ -0,0 +1,90 @@
+// ServerPool against a backend on a local TCP socket that writes a
+// scripted reply as soon as it accepts the connection.
+#![cfg(feature = "std")]
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use rsfcgi::fcgi::{
+    EndRequest, FcgiError, ProtocolStatus, Record, RecordType, ServerBuilder, ServerPool,
+};
+
+fn record(record_type: RecordType, content: &[u8]) -> Vec<u8> {
+    Record::record_from_data(record_type, content.to_vec(), 0)
+        .expect("content fits")
+        .to_vec_u8()
+}
+
+fn end() -> Vec<u8> {
+    let body = EndRequest::new(0, ProtocolStatus::RequestComplete).to_vec_u8();
+    record(RecordType::EndRequest, &body)
+}
+
+// Accepts one connection and writes `reply`, then reads until the client
+// hangs up. With `hang_up`, the backend stops writing right after `reply`.
+fn backend(reply: Vec<u8>, hang_up: bool) -> Result<(String, JoinHandle<()>), FcgiError> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?.to_string();
+    let handle = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().expect("accept");
+        conn.write_all(&reply).expect("write reply");
+        if hang_up {
+            conn.shutdown(Shutdown::Write).expect("shutdown");
+        }
+        let _ = conn.read_to_end(&mut Vec::new());
+    });
+
+    Ok((addr, handle))
+}
+
+// Reads the first byte of a response and drops the connection.
+fn read_one_byte(pool: &ServerPool<TcpStream>) -> Result<(), FcgiError> {
+    let mut server = pool.acquire()?;
+    let flags = server.request_flags();
+    server.send_begin(flags)?;
+    server.send_params()?;
+    server.finish_stdin()?;
+
+    let mut byte = [0; 1];
+    server.response_body().read_exact(&mut byte)?;
+    assert_eq!(&byte, b"\r");
+    assert!(server.request_in_flight());
+    Ok(())
+}
+
+#[test]
+fn drop_drains_the_unread_response() -> Result<(), FcgiError> {
+    let mut reply = record(RecordType::Stdout, b"\r\n\r\nfirst");
+    reply.extend(record(RecordType::Stdout, b""));
+    reply.extend(end());
+    reply.extend(record(RecordType::Stdout, b"\r\n\r\nsecond"));
+    reply.extend(end());
+    let (addr, handle) = backend(reply, false)?;
+
+    let pool = ServerPool::new(ServerBuilder::<TcpStream>::new(&addr));
+    read_one_byte(&pool)?;
+    assert_eq!(pool.idle_count(), 1);
+
+    let mut server = pool.acquire()?;
+    assert!(!server.request_in_flight());
+    assert_eq!(server.execute(b"")?.body(), b"second");
+    assert!(!server.request_in_flight());
+    drop(server);
+    drop(pool);
+    handle.join().expect("backend thread");
+    Ok(())
+}
+
+#[test]
+fn drop_closes_a_connection_that_cannot_be_drained() -> Result<(), FcgiError> {
+    let (addr, handle) = backend(record(RecordType::Stdout, b"\r\n\r\npartial"), true)?;
+
+    let pool = ServerPool::new(ServerBuilder::<TcpStream>::new(&addr));
+    read_one_byte(&pool)?;
+    assert_eq!(pool.idle_count(), 0);
+    assert_eq!(pool.open_count(), 0);
+    drop(pool);
+    handle.join().expect("backend thread");
+    Ok(())
+}
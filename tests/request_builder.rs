@@ -0,0 +1,63 @@
+use rsfcgi::fcgi::{FcgiError, PaddingPolicy, Record, RecordType, RequestBuilder};
+
+fn records(mut bytes: &[u8]) -> Result<Vec<Record>, FcgiError> {
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let (record, used) = Record::from_bytes(bytes)?;
+        records.push(record);
+        bytes = &bytes[used..];
+    }
+
+    Ok(records)
+}
+
+#[test]
+fn max_record_len_splits_streams() -> Result<(), FcgiError> {
+    let bytes = RequestBuilder::new()
+        .request_id(3)
+        .max_record_len(4)
+        .stdin(b"abcdefghij".to_vec())
+        .build()?;
+    let records = records(&bytes)?;
+
+    let stdin: Vec<&[u8]> = records
+        .iter()
+        .filter(|r| r.record_type() == RecordType::Stdin)
+        .map(|r| r.content())
+        .collect();
+    assert_eq!(stdin, [&b"abcd"[..], b"efgh", b"ij", b""]);
+    assert!(records.iter().all(|r| r.request_id() == 3));
+    Ok(())
+}
+
+// Every record is padded, BeginRequest and the empty ones included.
+#[test]
+fn padding_applies_to_every_record() -> Result<(), FcgiError> {
+    let bytes = RequestBuilder::new()
+        .padding(PaddingPolicy::Align8)
+        .param("SCRIPT_NAME", "/a.php")
+        .stdin(b"abc".to_vec())
+        .build()?;
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let (_, used) = Record::from_bytes(rest)?;
+        assert_eq!(used % 8, 0);
+        rest = &rest[used..];
+    }
+
+    let fixed = RequestBuilder::new().padding(PaddingPolicy::Fixed(3)).build()?;
+    assert!(fixed.chunks(8).next().is_some_and(|header| header[6] == 3));
+    Ok(())
+}
+
+// By default records aren't padded and hold up to u16::MAX bytes.
+#[test]
+fn defaults() -> Result<(), FcgiError> {
+    let bytes = RequestBuilder::new().stdin(vec![b'x'; 70_000]).build()?;
+    let records = records(&bytes)?;
+
+    assert!(records.iter().all(|r| r.to_vec_u8().len() == 8 + r.content().len()));
+    let stdin = records.iter().filter(|r| r.record_type() == RecordType::Stdin);
+    assert_eq!(stdin.count(), 3);
+    Ok(())
+}
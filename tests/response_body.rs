@@ -0,0 +1,75 @@
+#![cfg(feature = "std")]
+
+use std::io::Read;
+
+use rsfcgi::fcgi::{FcgiError, MockBackend, Server};
+
+// Some apps end the response without the empty Stdout record; EndRequest
+// alone still gives EOF.
+#[test]
+fn eof_at_end_request_without_empty_stdout() -> Result<(), FcgiError> {
+    let backend = MockBackend::new().stdout(b"one ").stdout(b"two").end(3);
+    let mut server = Server::from_stream(Vec::new(), backend);
+    let mut body = server.response_body();
+
+    let mut out = Vec::new();
+    body.read_to_end(&mut out)?;
+    assert_eq!(out, b"one two");
+    assert_eq!(body.end_request().map(|end| end.app_status()), Some(3));
+    Ok(())
+}
+
+// Once EndRequest has been read nothing more is read from the connection,
+// which here has nothing left and would fail.
+#[test]
+fn reads_after_eof_return_zero() -> Result<(), FcgiError> {
+    let backend = MockBackend::new().stdout(b"body").stdout(b"").end(0);
+    let mut server = Server::from_stream(Vec::new(), backend);
+    let mut body = server.response_body();
+
+    let mut out = Vec::new();
+    body.read_to_end(&mut out)?;
+    let mut buf = [0; 16];
+    assert_eq!(body.read(&mut buf)?, 0);
+    assert_eq!(body.read(&mut buf)?, 0);
+    assert_eq!(out, b"body");
+    Ok(())
+}
+
+#[test]
+fn stderr_kept_out_of_the_body() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .stderr(b"first ")
+        .stdout(b"a")
+        .stderr(b"second")
+        .stdout(b"b")
+        .stderr(b"")
+        .stdout(b"")
+        .end(0);
+    let mut server = Server::from_stream(Vec::new(), backend);
+    let mut body = server.response_body();
+
+    let mut out = Vec::new();
+    body.read_to_end(&mut out)?;
+    assert_eq!(out, b"ab");
+    assert_eq!(body.stderr(), b"first second");
+    Ok(())
+}
+
+// Reads smaller than a record hand its content out piece by piece.
+#[test]
+fn small_reads() -> Result<(), FcgiError> {
+    let backend = MockBackend::new().stdout(b"abcde").end(0);
+    let mut server = Server::from_stream(Vec::new(), backend);
+    let mut body = server.response_body();
+
+    let mut buf = [0; 2];
+    assert_eq!(body.read(&mut buf)?, 2);
+    assert_eq!(&buf, b"ab");
+    assert_eq!(body.read(&mut buf)?, 2);
+    assert_eq!(&buf, b"cd");
+    assert_eq!(body.read(&mut buf)?, 1);
+    assert_eq!(&buf[..1], b"e");
+    assert_eq!(body.read(&mut buf)?, 0);
+    Ok(())
+}
@@ -0,0 +1,231 @@
+// The response loop run against scripted MockBackend replies, for the
+// orderings and edge cases apps actually produce.
+#![cfg(feature = "std")]
+
+use std::sync::{Arc, Mutex};
+
+use rsfcgi::fcgi::{
+    BeginRequest, FcgiError, FcgiResponse, KeyValuePair, MockBackend, ParsedRecordType, Record,
+    RecordType, RoleType, Server,
+};
+
+fn respond(backend: MockBackend) -> Result<FcgiResponse, FcgiError> {
+    Server::from_stream(Vec::new(), backend).consume_response()
+}
+
+#[test]
+fn status_headers_and_streams() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .stdout(b"Status: 404 Not Found\r\nContent-Type: text/plain\r\n\r\nmissing")
+        .stdout(b"")
+        .stderr(b"warning")
+        .stderr(b"")
+        .end(0);
+    let response = respond(backend)?;
+
+    assert_eq!(response.http_status, 404);
+    assert_eq!(response.header("content-type"), Some("text/plain"));
+    assert_eq!(response.body(), b"missing");
+    assert_eq!(response.stderr, b"warning");
+    assert!(response.is_success());
+    Ok(())
+}
+
+// php-fpm logs notices before the script prints anything.
+#[test]
+fn stderr_before_stdout() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .stderr(b"PHP Notice: early\n")
+        .stdout(b"Content-Type: text/html\r\n\r\n<p>")
+        .stderr(b"PHP Notice: late\n")
+        .stdout(b"</p>")
+        .end(0);
+    let response = respond(backend)?;
+
+    assert_eq!(response.body(), b"<p></p>");
+    assert_eq!(response.header("Content-Type"), Some("text/html"));
+    assert_eq!(response.stderr, b"PHP Notice: early\nPHP Notice: late\n");
+    assert_eq!(response.stderr_lines().count(), 2);
+    Ok(())
+}
+
+// Header parsing runs over all of stdout, so a header line, or the blank
+// line ending the headers, may be cut anywhere by a record boundary.
+#[test]
+fn header_split_across_records() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .stdout(b"Content-Type: text/ht")
+        .stdout(b"ml\r\n\r")
+        .stdout(b"\nbody")
+        .stdout(b"")
+        .end(0);
+    let response = respond(backend)?;
+
+    assert_eq!(response.header("Content-Type"), Some("text/html"));
+    assert_eq!(response.body(), b"body");
+    Ok(())
+}
+
+#[test]
+fn latin1_body() -> Result<(), FcgiError> {
+    let backend = MockBackend::new()
+        .stdout(b"Content-Type: text/html; charset=\"ISO-8859-1\"\r\n\r\ncaf\xe9")
+        .end(0);
+
+    assert_eq!(respond(backend)?.body_as_string(), "caf\u{e9}");
+    Ok(())
+}
+
+// Nothing but the record closing Stdout: no headers, no body.
+#[test]
+fn empty_stdout() -> Result<(), FcgiError> {
+    let response = respond(MockBackend::new().stdout(b"").end(0))?;
+
+    assert!(response.stdout.is_empty());
+    assert!(response.body().is_empty());
+    assert_eq!(response.http_status, 200);
+    assert!(response.is_success());
+    Ok(())
+}
+
+#[test]
+fn nonzero_app_status() -> Result<(), FcgiError> {
+    let backend = MockBackend::new().stdout(b"\r\n\r\n").stdout(b"").end(255);
+    let response = respond(backend)?;
+
+    assert_eq!(response.app_status, 255);
+    assert!(!response.is_success());
+    Ok(())
+}
+
+// With an on_stderr hook stderr goes there as it arrives instead.
+#[test]
+fn on_stderr_hook() -> Result<(), FcgiError> {
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    let backend = MockBackend::new().stderr(b"one").stdout(b"\r\n\r\n").stderr(b"two").end(0);
+    let mut server = Server::from_stream(Vec::new(), backend);
+    server.set_on_stderr(move |line| sink.lock().unwrap().push(line.to_vec()));
+    let response = server.consume_response()?;
+
+    assert!(response.stderr.is_empty());
+    assert_eq!(*logged.lock().unwrap(), [b"one", b"two"]);
+    Ok(())
+}
+
+// A GetValuesResult for the request, 3 bytes long, mid-response.
+#[test]
+fn unexpected_record() {
+    let backend = MockBackend::new()
+        .stdout(b"\r\n\r\n")
+        .raw(&[1, RecordType::GetValuesResult as u8, 0, 1, 0, 3, 0, 0, b'a', b'b', b'c'])
+        .end(0);
+
+    assert!(matches!(
+        respond(backend),
+        Err(FcgiError::UnexpectedRecord {
+            record_type: ParsedRecordType::Known(RecordType::GetValuesResult),
+            length: 3
+        })
+    ));
+}
+
+// A type byte the spec doesn't define is reported as it was read, with
+// its length, rather than as a generic protocol error.
+#[test]
+fn undefined_record_type() {
+    let backend = MockBackend::new()
+        .stdout(b"\r\n\r\n")
+        .raw(&[1, 42, 0, 1, 0, 2, 0, 0, b'o', b'k'])
+        .end(0);
+
+    let err = respond(backend).expect_err("type 42 is not a response record");
+    assert_eq!(err.to_string(), "unexpected unknown record type 42 of 2 bytes in a response");
+    assert!(matches!(
+        err,
+        FcgiError::UnexpectedRecord {
+            record_type: ParsedRecordType::Unknown(42),
+            length: 2
+        }
+    ));
+}
+
+// consume_response_with sees every record, padding and EndRequest
+// included, and the response is built as usual.
+#[test]
+fn consume_response_with_sees_records() -> Result<(), FcgiError> {
+    let padded = Record::record_aligned(RecordType::Stdout, b"\r\n\r\nok".to_vec())?.to_vec_u8();
+    let backend = MockBackend::new().raw(&padded).stdout(b"").end(0);
+    let mut seen = Vec::new();
+    let response = Server::from_stream(Vec::new(), backend)
+        .consume_response_with(|record| seen.push((record.record_type(), record.to_vec_u8())))?;
+
+    assert_eq!(seen.len(), 3);
+    assert_eq!(seen[0].1, padded);
+    assert_eq!(seen[2].0, RecordType::EndRequest);
+    assert_eq!(response.body(), b"ok");
+    Ok(())
+}
+
+#[test]
+fn response_byte_limit() {
+    let backend = MockBackend::new().stdout(b"abc").stdout(b"de").end(0);
+    let mut server = Server::from_stream(Vec::new(), backend);
+    server.set_response_limits(4, usize::MAX);
+
+    assert!(matches!(
+        server.consume_response(),
+        Err(FcgiError::ResponseTooLarge {
+            bytes: 5,
+            records: 2
+        })
+    ));
+}
+
+// A Stdout record for request id 2, left over from an earlier request.
+fn stale_server() -> Result<Server<MockBackend>, FcgiError> {
+    let old = Record::record_for_request(RecordType::Stdout, b"old".to_vec(), 0, 2)?;
+    let backend = MockBackend::new().record(old).stdout(b"\r\n\r\nnew").end(0);
+
+    Ok(Server::from_stream(Vec::new(), backend))
+}
+
+#[test]
+fn stale_record_fails_when_strict() -> Result<(), FcgiError> {
+    assert!(matches!(
+        stale_server()?.consume_response(),
+        Err(FcgiError::RequestIdMismatch {
+            expected: 1,
+            got: 2
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn stale_record_skipped_when_lenient() -> Result<(), FcgiError> {
+    let mut server = stale_server()?;
+    server.set_strict_request_ids(false);
+
+    assert_eq!(server.consume_response()?.body(), b"new");
+    Ok(())
+}
+
+// Server::request sends the params it's given, not the stored ones.
+#[test]
+fn request_sends_its_own_params() -> Result<(), FcgiError> {
+    let stored = vec![(String::from("STORED"), String::from("1"))];
+    let mut server = Server::from_stream(stored, MockBackend::new().stdout(b"").end(0));
+    let begin = BeginRequest::new(RoleType::Responder, 0, [0; 5]);
+    server.request(begin, &[(String::from("OWN"), String::from("2"))], b"")?;
+
+    let params: Vec<u8> = server
+        .get_ref()
+        .sent_records()?
+        .iter()
+        .filter(|r| r.record_type() == RecordType::Params)
+        .flat_map(|r| r.content().to_vec())
+        .collect();
+    assert_eq!(params, KeyValuePair::new("OWN".into(), "2".into()).to_vec_u8()?);
+    Ok(())
+}
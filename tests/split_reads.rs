@@ -0,0 +1,38 @@
+// Responses from a connection that hands back one byte per read, so every
+// header and body arrives across many reads.
+#![cfg(feature = "std")]
+
+use std::io::Read;
+
+use rsfcgi::fcgi::{FcgiError, MockBackend, Record, RecordType, Server};
+
+#[test]
+fn one_byte_reads() -> Result<(), FcgiError> {
+    let body = "x".repeat(1000);
+    let backend = MockBackend::new()
+        .stderr(b"notice")
+        .stdout(format!("Content-Type: text/plain\r\n\r\n{}", body).as_bytes())
+        .stdout(b"")
+        .end(7)
+        .max_read(1);
+    let response = Server::from_stream(Vec::new(), backend).consume_response()?;
+
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(response.body(), body.as_bytes());
+    assert_eq!(response.stderr, b"notice");
+    assert_eq!(response.app_status, 7);
+    Ok(())
+}
+
+// Padding is skipped however it is split up.
+#[test]
+fn padded_records_in_small_reads() -> Result<(), FcgiError> {
+    let padded = Record::record_aligned(RecordType::Stdout, b"\r\n\r\nabc".to_vec())?;
+    let backend = MockBackend::new().record(padded).stdout(b"def").end(0).max_read(3);
+    let mut server = Server::from_stream(Vec::new(), backend);
+
+    let mut out = Vec::new();
+    server.response_body().read_to_end(&mut out)?;
+    assert_eq!(out, b"\r\n\r\nabcdef");
+    Ok(())
+}
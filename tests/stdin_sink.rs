@@ -0,0 +1,66 @@
+#![cfg(feature = "std")]
+
+use std::io::Write;
+
+use rsfcgi::fcgi::{
+    FcgiError, MockBackend, PaddingPolicy, Record, RecordType, Server, StdinSink,
+};
+
+fn records(mut bytes: &[u8]) -> Result<Vec<(Record, usize)>, FcgiError> {
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let (record, used) = Record::from_bytes(bytes)?;
+        records.push((record, used));
+        bytes = &bytes[used..];
+    }
+
+    Ok(records)
+}
+
+// Server::stdin_sink frames the body the way the server's own records
+// are framed.
+#[test]
+fn server_sink_uses_the_server_settings() -> Result<(), FcgiError> {
+    let mut server = Server::from_stream(Vec::new(), MockBackend::new());
+    server.set_padding_policy(PaddingPolicy::Align8);
+    server.set_max_record_len(4);
+    let mut sink = server.stdin_sink();
+    sink.write_all(b"abcdefghij")?;
+    sink.finish()?;
+
+    let sent = records(server.get_ref().sent())?;
+    let stdin: Vec<&[u8]> = sent.iter().map(|(r, _)| r.content()).collect();
+    assert_eq!(stdin, [&b"abcd"[..], b"efgh", b"ij", b""]);
+    assert!(sent.iter().all(|(r, used)| r.record_type() == RecordType::Stdin && used % 8 == 0));
+    assert!(sent.iter().all(|(r, _)| r.request_id() == server.request_id()));
+    Ok(())
+}
+
+#[test]
+fn sink_settings() -> Result<(), FcgiError> {
+    let mut sink = StdinSink::new(Vec::new())
+        .request_id(9)
+        .padding(PaddingPolicy::Fixed(2))
+        .max_record_len(3);
+    sink.write_all(b"hello")?;
+    let sent = records(&sink.finish()?)?;
+
+    let stdin: Vec<&[u8]> = sent.iter().map(|(r, _)| r.content()).collect();
+    assert_eq!(stdin, [&b"hel"[..], b"lo", b""]);
+    assert!(sent.iter().all(|(r, used)| r.request_id() == 9 && *used == 8 + r.content().len() + 2));
+    Ok(())
+}
+
+// Without settings records hold up to u16::MAX bytes, unpadded, for
+// request id 1.
+#[test]
+fn sink_defaults() -> Result<(), FcgiError> {
+    let mut sink = StdinSink::new(Vec::new());
+    sink.write_all(&vec![b'x'; 70_000])?;
+    let sent = records(&sink.finish()?)?;
+
+    let lengths: Vec<usize> = sent.iter().map(|(r, _)| r.content().len()).collect();
+    assert_eq!(lengths, [65535, 70_000 - 65535, 0]);
+    assert!(sent.iter().all(|(r, used)| r.request_id() == 1 && *used == 8 + r.content().len()));
+    Ok(())
+}
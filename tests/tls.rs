@@ -0,0 +1,115 @@
+// server_from_tls against a backend on a local TCP socket that speaks TLS
+// with a self-signed certificate for "localhost".
+#![cfg(feature = "tls")]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use rsfcgi::fcgi::{
+    server_from_tls, EndRequest, FcgiError, HeaderView, ProtocolStatus, Record, RecordType,
+};
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ClientConfig, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+fn record(record_type: RecordType, content: &[u8]) -> Vec<u8> {
+    Record::record_from_data(record_type, content.to_vec(), 0)
+        .expect("content fits")
+        .to_vec_u8()
+}
+
+// A server config for a fresh certificate, and a client config that
+// trusts only that certificate.
+fn configs() -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).expect("cert");
+    let cert = CertificateDer::from(certified.cert.der().to_vec());
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+
+    let server = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("protocol versions")
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.clone()], PrivateKeyDer::Pkcs8(key))
+        .expect("server config");
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).expect("root certificate");
+    let client = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    (Arc::new(server), Arc::new(client))
+}
+
+// Accepts one TLS connection, reads the request up to the empty Stdin
+// record and writes `reply`. Returns the port and the records' types.
+fn backend(
+    config: Arc<ServerConfig>,
+    reply: Vec<u8>,
+) -> Result<(u16, JoinHandle<Vec<u8>>), FcgiError> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = thread::spawn(move || {
+        let (sock, _) = listener.accept().expect("accept");
+        let conn = ServerConnection::new(config).expect("server connection");
+        let mut tls = StreamOwned::new(conn, sock);
+
+        let mut types = Vec::new();
+        loop {
+            let mut header = [0; 8];
+            tls.read_exact(&mut header).expect("record header");
+            let view = HeaderView::new(&header);
+            let mut body = vec![0; view.body_length()];
+            tls.read_exact(&mut body).expect("record body");
+            types.push(view.type_byte());
+            if view.type_byte() == RecordType::Stdin as u8 && view.content_length() == 0 {
+                break;
+            }
+        }
+        tls.write_all(&reply).expect("write reply");
+        tls.conn.send_close_notify();
+        tls.flush().expect("flush");
+        types
+    });
+
+    Ok((port, handle))
+}
+
+#[test]
+fn request_over_tls() -> Result<(), FcgiError> {
+    let (server_config, client_config) = configs();
+    let mut reply = record(RecordType::Stdout, b"Content-Type: text/plain\r\n\r\nsecret");
+    reply.extend(record(RecordType::Stdout, b""));
+    let end = EndRequest::new(0, ProtocolStatus::RequestComplete).to_vec_u8();
+    reply.extend(record(RecordType::EndRequest, &end));
+    let (port, handle) = backend(server_config, reply)?;
+
+    let params = vec![(String::from("SCRIPT_FILENAME"), String::from("/srv/index.php"))];
+    let mut server = server_from_tls(params, "localhost", port, client_config)?;
+    let response = server.execute(b"")?;
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(response.body(), b"secret");
+
+    let types = handle.join().expect("backend thread");
+    assert_eq!(types.first(), Some(&(RecordType::BeginRequest as u8)));
+    assert_eq!(types.last(), Some(&(RecordType::Stdin as u8)));
+    Ok(())
+}
+
+#[test]
+fn refused_connection_names_the_address() -> Result<(), FcgiError> {
+    let (_, client_config) = configs();
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    drop(listener);
+
+    match server_from_tls(Vec::new(), "127.0.0.1", port, client_config) {
+        Err(FcgiError::ConnectionRefused(addr)) => assert_eq!(addr, format!("127.0.0.1:{}", port)),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("connected to a closed port"),
+    }
+    Ok(())
+}
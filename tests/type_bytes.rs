@@ -0,0 +1,58 @@
+use rsfcgi::fcgi::{FcgiError, RecordType, RoleType};
+
+const RECORD_TYPES: [RecordType; 11] = [
+    RecordType::BeginRequest,
+    RecordType::AbortRequest,
+    RecordType::EndRequest,
+    RecordType::Params,
+    RecordType::Stdin,
+    RecordType::Stdout,
+    RecordType::Stderr,
+    RecordType::Data,
+    RecordType::GetValues,
+    RecordType::GetValuesResult,
+    RecordType::UnknownType,
+];
+
+const ROLES: [RoleType; 3] = [RoleType::Responder, RoleType::Authorizer, RoleType::Filter];
+
+// The spec numbers record types 1 through 11, in this order.
+// https://www.mit.edu/~yandros/doc/specs/fcgi-spec.html#S8
+#[test]
+fn record_type_bytes() -> Result<(), FcgiError> {
+    for (byte, record_type) in (1..).zip(RECORD_TYPES) {
+        assert_eq!(record_type as u8, byte);
+        assert_eq!(RecordType::try_from(byte)?, record_type);
+    }
+    Ok(())
+}
+
+#[test]
+fn undefined_record_types() {
+    assert!(matches!(RecordType::try_from(0), Err(FcgiError::Protocol(_))));
+    assert!(matches!(RecordType::try_from(12), Err(FcgiError::Protocol(_))));
+}
+
+#[test]
+fn role_bytes() -> Result<(), FcgiError> {
+    for (byte, role) in (1..).zip(ROLES) {
+        assert_eq!(role as u8, byte);
+        assert_eq!(RoleType::try_from(byte)?, role);
+        assert_eq!(RoleType::try_from(u16::from(byte))?, role);
+    }
+    Ok(())
+}
+
+#[test]
+fn undefined_roles() {
+    assert!(matches!(RoleType::try_from(0u8), Err(FcgiError::Protocol(_))));
+    assert!(matches!(RoleType::try_from(4u8), Err(FcgiError::Protocol(_))));
+    assert!(matches!(RoleType::try_from(0x0101u16), Err(FcgiError::Protocol(_))));
+}
+
+#[test]
+fn display_uses_the_spec_names() {
+    assert_eq!(RecordType::GetValuesResult.to_string(), "FCGI_GET_VALUES_RESULT");
+    assert_eq!(RecordType::Stdin.to_string(), "FCGI_STDIN");
+    assert_eq!(RoleType::Authorizer.to_string(), "FCGI_AUTHORIZER");
+}